@@ -1,7 +1,41 @@
-use bevy::{prelude::*, utils::HashSet};
+use std::f32::consts::PI;
 
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+// the index of the tank, the first one is always played with mouse and keyboard
+#[derive(Component)]
+pub struct Player(pub usize);
+
+#[derive(Component, Clone, Copy)]
+pub enum Controls {
+    Mouse,
+    Gamepad(Gamepad),
+}
+
+// the direction a tank's barrel is pointing in
+#[derive(Component)]
+pub struct Aim(pub Vec2);
+
+// a tank that ran out of health, it sits out the rest of the run
+#[derive(Component)]
+pub struct Downed;
+
+// an enemy that can't move or attack until the timer runs out
+#[derive(Component, Deref, DerefMut)]
+pub struct Stunned(pub Timer);
+
+// speeds up a tank's shooting until the timer runs out
+#[derive(Component, Deref, DerefMut)]
+pub struct Overdrive(pub Timer);
+
+// pulls every coin towards a tank until the timer runs out
+#[derive(Component, Deref, DerefMut)]
+pub struct Magnet(pub Timer);
+
+// whatever fired a bullet, a tank or one of its companions
 #[derive(Component)]
-pub struct Player;
+pub struct Owner(pub Entity);
 
 #[derive(Component)]
 pub struct Enemy;
@@ -18,6 +52,14 @@ pub struct Bullet;
 #[derive(Component, Deref, DerefMut)]
 pub struct AttackTimer(pub Timer);
 
+// only tanks in runs with the overheat mutator have this, every volley heats the barrel up and
+// once it's full the barrel locks until it has cooled down completely
+#[derive(Component, Default)]
+pub struct Heat {
+    pub value: f32,
+    pub overheated: bool,
+}
+
 // this is horribly misnamed but it's basically a request for an animation to stop :)
 #[derive(Component)]
 pub struct Ready(pub bool);
@@ -31,8 +73,33 @@ impl Health {
     }
 }
 
+// maps every enemy a bullet has hit to the time (in seconds since startup) it was hit
 #[derive(Component, Default)]
-pub struct HitEnemies(pub HashSet<Entity>);
+pub struct HitEnemies(pub HashMap<Entity, f64>);
+
+// bullets with this can hit the same enemy again once this many seconds have passed
+#[derive(Component)]
+pub struct RehitCooldown(pub f64);
+
+// number of times a bullet can still bounce off the edges of the arena
+#[derive(Component)]
+pub struct Ricochet(pub u32);
+
+// flies outwards until the timer finishes, then heads back to the player
+#[derive(Component, Deref, DerefMut)]
+pub struct Boomerang(pub Timer);
+
+// stuns the enemies a bullet hits for this many seconds
+#[derive(Component)]
+pub struct Frost(pub f32);
+
+// heals whatever fired a bullet by this share of the damage it deals
+#[derive(Component)]
+pub struct Leech(pub f32);
+
+// purely visual, in radians per second
+#[derive(Component)]
+pub struct Spin(pub f32);
 
 #[derive(Component, Clone)]
 pub struct Pierce(pub i32);
@@ -55,6 +122,275 @@ impl Default for Damage {
 #[derive(Component, Clone, Default)]
 pub struct Knockback(pub f32);
 
+#[derive(Component, Clone)]
+pub struct Crit {
+    pub chance: f32,
+    pub multiplier: f32,
+}
+
+impl Default for Crit {
+    fn default() -> Crit {
+        Crit {
+            chance: 0.05,
+            multiplier: 2.0,
+        }
+    }
+}
+
+// a turn rate of 0 means bullets fly straight
+#[derive(Component, Clone, Default)]
+pub struct Homing {
+    // in radians per second
+    pub turn_rate: f32,
+    pub range: f32,
+}
+
+impl Homing {
+    pub fn next(&mut self) {
+        if self.turn_rate == 0.0 {
+            self.turn_rate = 3.0;
+            self.range = 350.0;
+        } else {
+            self.turn_rate = 6.0;
+            self.range = 600.0;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Spread(pub u32, pub f32);
+
+impl Spread {
+    pub fn next(&mut self) {
+        if self.0 == 0 {
+            self.1 = PI / 16.0;
+
+            self.0 += 1;
+        } else if self.0 == 1 {
+            self.1 = 0.0;
+
+            self.0 += 1;
+        }
+    }
+}
+
+impl Default for Spread {
+    fn default() -> Spread {
+        Spread(0, PI / 8.0)
+    }
+}
+
+// describes how every tick of the `ShootTimer` turns into bullets, `Spread` is applied on top
+#[derive(Clone)]
+pub struct FirePattern {
+    // bullets per volley, fanned out evenly across `fan_angle`
+    pub shots: u32,
+    pub fan_angle: f32,
+    // volleys per tick, `burst_delay` seconds apart
+    pub burst: u32,
+    pub burst_delay: f32,
+    // alternate volleys between two barrels on either side of the tank
+    pub twin: bool,
+    // an extra bullet straight backwards every volley
+    pub rear: bool,
+}
+
+impl FirePattern {
+    pub fn directions(&self, aim: Vec2) -> Vec<Vec2> {
+        let mut directions: Vec<Vec2> = (0..self.shots)
+            .map(|i| {
+                let angle = if self.shots == 1 {
+                    0.0
+                } else {
+                    self.fan_angle * (i as f32 / (self.shots - 1) as f32 - 0.5)
+                };
+
+                Vec2::from_angle(angle).rotate(aim)
+            })
+            .collect();
+
+        if self.rear {
+            directions.push(-aim);
+        }
+
+        directions
+    }
+}
+
+impl Default for FirePattern {
+    fn default() -> FirePattern {
+        FirePattern {
+            shots: 1,
+            fan_angle: PI / 6.0,
+            burst: 1,
+            burst_delay: 0.05,
+            twin: false,
+            rear: false,
+        }
+    }
+}
+
+// the state of the burst currently being fired
+#[derive(Component, Default)]
+pub struct Volley {
+    pub shots_left: u32,
+    pub timer: Timer,
+    pub left_barrel: bool,
+    // how far the trigger was wound up before the burst, from 0 to 1
+    pub charge: f32,
+}
+
+// how far a tank with a charged shot has wound up its trigger, from 0 to 1
+#[derive(Component, Default, Deref, DerefMut)]
+pub struct Charge(pub f32);
+
+// carried by every bullet so hits know what they were hit with
+#[derive(Component, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum BulletType {
+    #[default]
+    Regular = 0,
+    Rocket = 1,
+    SawBlade = 2,
+}
+
+impl BulletType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BulletType::Regular => "Bullet",
+            BulletType::Rocket => "Rocket",
+            BulletType::SawBlade => "Sawblade",
+        }
+    }
+}
+
+// everything the skill tree can change about a tank's weapon
+#[derive(Component, Clone)]
+pub struct Loadout {
+    pub bullet_type: BulletType,
+    pub damage: Damage,
+    pub crit: Crit,
+    pub pierce: Pierce,
+    pub knockback: Knockback,
+    pub spread: Spread,
+    pub homing: Homing,
+    pub pattern: FirePattern,
+    pub saw_blade: SawBladeStats,
+    // seconds between ticks of the `ShootTimer`
+    pub fire_interval: f32,
+    // how much `Heat` the barrel holds and how much of it goes away per second
+    pub heat_capacity: f32,
+    pub cooling: f32,
+    // seconds to a full charge, without it the barrel fires on its own while the trigger is held
+    pub charge_time: Option<f32>,
+    // bullets are scaled by this on top of the size of their type
+    pub bullet_size: f32,
+    // seconds a hit stuns for and the share of damage healed back, zero for neither
+    pub frost: f32,
+    pub leech: f32,
+}
+
+impl Loadout {
+    // human readable stats, used to show what an upgrade changes
+    pub fn stats(&self) -> Vec<(&'static str, String)> {
+        let yes_no = |b: bool| if b { "Yes" } else { "No" }.to_string();
+
+        vec![
+            ("Bullet", self.bullet_type.name().to_string()),
+            ("Damage", format!("{:.0}", self.damage.0)),
+            ("Pierce", format!("{}", self.pierce.0)),
+            ("Knockback", format!("{:.0}", self.knockback.0)),
+            ("Fire interval", format!("{:.3}s", self.fire_interval)),
+            ("Spread", format!("{:.1} deg", self.spread.1.to_degrees())),
+            ("Bullets per volley", format!("{}", self.pattern.shots)),
+            ("Volleys per burst", format!("{}", self.pattern.burst)),
+            ("Twin barrels", yes_no(self.pattern.twin)),
+            ("Rear gun", yes_no(self.pattern.rear)),
+            ("Crit chance", format!("{:.0}%", self.crit.chance * 100.0)),
+            ("Crit multiplier", format!("x{:.1}", self.crit.multiplier)),
+            ("Homing turn rate", format!("{:.1}", self.homing.turn_rate)),
+            ("Homing range", format!("{:.0}", self.homing.range)),
+            ("Sawblade bounces", format!("{}", self.saw_blade.bounces)),
+            ("Boomerang", yes_no(self.saw_blade.boomerang)),
+            ("Frost stun", format!("{:.1}s", self.frost)),
+            ("Leech", format!("{:.0}%", self.leech * 100.0)),
+            ("Heat capacity", format!("{:.0}", self.heat_capacity)),
+            ("Cooling", format!("{:.0}/s", self.cooling)),
+            (
+                "Charge time",
+                self.charge_time
+                    .map_or("-".to_string(), |time| format!("{:.1}s", time)),
+            ),
+        ]
+    }
+}
+
+impl Default for Loadout {
+    fn default() -> Loadout {
+        Loadout {
+            bullet_type: default(),
+            damage: default(),
+            crit: default(),
+            pierce: default(),
+            knockback: default(),
+            spread: default(),
+            homing: default(),
+            pattern: default(),
+            saw_blade: default(),
+            fire_interval: 0.125,
+            heat_capacity: 100.0,
+            cooling: 20.0,
+            charge_time: None,
+            bullet_size: 1.0,
+            frost: 0.0,
+            leech: 0.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SawBladeStats {
+    pub bounces: u32,
+    pub boomerang: bool,
+    pub rehit_cooldown: f64,
+}
+
+impl Default for SawBladeStats {
+    fn default() -> SawBladeStats {
+        SawBladeStats {
+            bounces: 3,
+            boomerang: false,
+            rehit_cooldown: 0.3,
+        }
+    }
+}
+
+#[derive(Component, Deref, DerefMut)]
+pub struct ShootTimer(pub Timer);
+
+impl Default for ShootTimer {
+    fn default() -> ShootTimer {
+        ShootTimer(Timer::from_seconds(Loadout::default().fire_interval, true))
+    }
+}
+
+// everything a shooter needs to fire on its own, independent of any other shooter
+#[derive(Bundle)]
+pub struct WeaponBundle {
+    pub loadout: Loadout,
+    pub shoot_timer: ShootTimer,
+    pub volley: Volley,
+}
+
+impl WeaponBundle {
+    pub fn new(loadout: Loadout) -> WeaponBundle {
+        WeaponBundle {
+            shoot_timer: ShootTimer(Timer::from_seconds(loadout.fire_interval, true)),
+            loadout,
+            volley: default(),
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct HasHealthBar;
 
@@ -1,8 +1,16 @@
-use bevy::{app::AppExit, prelude::*, render::texture::ImageSettings};
+// bevy systems take their world access as arguments and queries spell out what they fetch
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    render::{camera::ScalingMode, texture::ImageSettings},
+    window::WindowMode,
+};
 use bevy_asset_loader::prelude::*;
 use bevy_pkv::PkvStore;
 use bevy_rapier2d::prelude::*;
-use components::{Coin, HasHealthBar, Health, HealthBar, Player};
+use components::{Coin, Enemy, HasHealthBar, Health, HealthBar, Player};
 use iyes_loopless::prelude::*;
 
 mod plugins;
@@ -20,23 +28,69 @@ pub enum GameState {
     Playing,
     SkillTree,
     GameOver,
+    Workshop,
+    Achievements,
+    Difficulty,
+    Mutators,
+    Stats,
 }
 
 #[derive(Component)]
 struct PlayButton;
 
+#[derive(Component)]
+struct ContinueButton;
+
+#[derive(Component)]
+struct DailyButton;
+
+#[derive(Component)]
+struct DifficultyButton;
+
+#[derive(Component)]
+struct WorkshopButton;
+
+#[derive(Component)]
+struct AchievementsButton;
+
+#[derive(Component)]
+struct StatsButton;
+
 #[derive(Component)]
 struct ExitButton;
 
+#[derive(Component)]
+struct ShakeButton;
+
+#[derive(Component)]
+struct ShakeText;
+
+#[derive(Component)]
+struct ExportButton;
+
+#[derive(Component)]
+struct ExportText;
+
+#[derive(Component)]
+struct CoOpButton;
+
+#[derive(Component)]
+struct CoOpText;
+
 #[derive(Component)]
 struct MainMenu;
 
+#[derive(Component)]
+struct Letterbox;
+
 fn main() {
     App::new()
         .insert_resource(ImageSettings::default_nearest())
         .insert_resource(WindowDescriptor {
             title: "Minijam 114".to_string(),
-            resizable: false,
+            width: ARENA_SIZE.x,
+            height: ARENA_SIZE.y,
+            resizable: true,
             ..default()
         })
         .insert_resource(RapierConfiguration {
@@ -54,25 +108,52 @@ fn main() {
         .add_plugins(DefaultPlugins)
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
         // Our plugins
+        .init_resource::<Settings>()
+        .add_startup_system(load_settings)
         .add_exit_system(GameState::Loading, spawn_camera)
         .add_plugin(PlayerPlugin)
         .add_plugin(EnemyPlugin)
         .add_plugin(SkillTreePlugin)
         .add_plugin(GameOverPlugin)
-        .add_enter_system(GameState::Menu, setup)
+        .add_plugin(DamageNumberPlugin)
+        .add_plugin(ParticlePlugin)
+        .add_plugin(CameraShakePlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(WorkshopPlugin)
+        .add_plugin(SavePlugin)
+        .add_plugin(AchievementsPlugin)
+        .add_plugin(StatsPlugin)
+        .add_plugin(DifficultyPlugin)
+        .add_plugin(ModifierPlugin)
+        .add_plugin(DailyPlugin)
+        .add_plugin(CompanionPlugin)
+        .add_plugin(AbilityPlugin)
+        .add_plugin(PowerUpPlugin)
+        .add_plugin(ProjectilePlugin)
+        .init_resource::<Score>()
+        .init_resource::<SurvivalTime>()
+        .add_enter_system(GameState::Menu, setup.after(load_difficulty))
         .add_exit_system(GameState::Menu, despawn_with::<MainMenu>)
         .add_system(update_buttons.run_in_state(GameState::Menu))
         .add_system(play.run_if(button_pressed::<PlayButton>))
+        .add_system(open_difficulty.run_if(button_pressed::<DifficultyButton>))
+        .add_system(open_workshop.run_if(button_pressed::<WorkshopButton>))
+        .add_system(open_achievements.run_if(button_pressed::<AchievementsButton>))
+        .add_system(open_stats.run_if(button_pressed::<StatsButton>))
         .add_system(exit.run_if(button_pressed::<ExitButton>))
+        .add_system(cycle_screen_shake.run_if(button_pressed::<ShakeButton>))
+        .add_system(toggle_export.run_if(button_pressed::<ExportButton>))
+        .add_system(toggle_co_op.run_if(button_pressed::<CoOpButton>))
         // health bar systems (could be a plugin but it's simple enough...)
         .add_system_to_stage(
             CoreStage::PreUpdate,
             remove_at_zero.run_in_state(GameState::Playing),
         )
         .add_system(collide_coins.run_in_state(GameState::Playing))
-        .add_system(update_coin_count.run_not_in_state(GameState::Loading))
+        .add_system(tick_survival_time.run_in_state(GameState::Playing))
         .add_system(update_healthbars)
         .add_system(insert_healthbars)
+        .add_system(toggle_fullscreen)
         .run();
 }
 
@@ -82,6 +163,11 @@ pub fn despawn_with<C: Component>(mut commands: Commands, q: Query<Entity, With<
     }
 }
 
+// true on the frame a run starts, before its player has been spawned
+pub fn run_starting(player: Query<(), With<Player>>) -> bool {
+    player.is_empty()
+}
+
 pub fn button_pressed<B: Component>(
     q: Query<&Interaction, (Changed<Interaction>, With<Button>, With<B>)>,
 ) -> bool {
@@ -107,53 +193,29 @@ pub fn update_buttons(
 }
 
 fn update_healthbars(
-    windows: Res<Windows>,
-    entities: Query<
-        (&Health, &Children, &Transform, Option<&Player>),
-        (With<HasHealthBar>, Without<HealthBar>),
-    >,
+    entities: Query<(&Health, &Children, &Transform), (With<HasHealthBar>, Without<HealthBar>)>,
     mut healthbars: Query<(&HealthBar, &mut Transform)>,
 ) {
-    for (health, children, parent_transform, maybe_player) in &entities {
+    for (health, children, parent_transform) in &entities {
         for child in children {
             if let Ok((healthbar, mut transform)) = healthbars.get_mut(*child) {
                 if healthbar.0 {
                     transform.scale.y = (health.0 / health.1) * 80.0 / parent_transform.scale.y;
                 }
-
-                if maybe_player.is_some() {
-                    let window = windows.primary();
-                    transform.translation = Vec3::new(
-                        (window.height() - 50.0) / 10.0,
-                        (window.width() - 150.0) / 10.0,
-                        if healthbar.0 { 11.0 } else { 10.0 },
-                    );
-                }
             }
         }
     }
 }
 
+// the player's health is shown in the HUD instead
 fn insert_healthbars(
     mut commands: Commands,
-    windows: Res<Windows>,
-    entities: Query<(Entity, &Transform, Option<&Player>), (With<Health>, Without<HasHealthBar>)>,
+    entities: Query<(Entity, &Transform), (With<Health>, Without<HasHealthBar>, Without<Player>)>,
 ) {
-    for (entity, transform, maybe_player) in &entities {
+    for (entity, transform) in &entities {
         commands
             .entity(entity)
             .with_children(|parent| {
-                let healthbar_pos = if maybe_player.is_some() {
-                    let window = windows.primary();
-                    Vec3::new(
-                        (window.height() - 50.0) / 10.0,
-                        (window.width() - 150.0) / 10.0,
-                        0.0,
-                    )
-                } else {
-                    Vec3::ZERO
-                };
-
                 parent
                     .spawn_bundle(SpriteBundle {
                         sprite: Sprite {
@@ -161,7 +223,7 @@ fn insert_healthbars(
                             custom_size: Some(Vec2::new(8.0, 1.0)),
                             ..default()
                         },
-                        transform: Transform::from_translation(healthbar_pos + Vec3::Z * 11.0)
+                        transform: Transform::from_translation(Vec3::Z * 11.0)
                             .with_scale(Vec3::new(1.0, 80.0, 1.0) / transform.scale),
                         ..default()
                     })
@@ -174,7 +236,7 @@ fn insert_healthbars(
                             custom_size: Some(Vec2::new(8.0, 1.0)),
                             ..default()
                         },
-                        transform: Transform::from_translation(healthbar_pos + Vec3::Z * 10.0)
+                        transform: Transform::from_translation(Vec3::Z * 10.0)
                             .with_scale(Vec3::new(1.0, 80.0, 1.0) / transform.scale),
                         ..default()
                     })
@@ -187,12 +249,34 @@ fn insert_healthbars(
 fn remove_at_zero(
     mut commands: Commands,
     sprites: Res<Sprites>,
-    entities: Query<(Entity, &Transform, &Health), Without<Player>>,
-    player: Query<&Transform, With<Player>>,
+    entities: Query<(Entity, &Transform, &Health, &Handle<Image>, &EnemyKind), With<Enemy>>,
+    tanks: Query<&Transform, With<Player>>,
+    mut particle_events: EventWriter<ParticleEvent>,
+    mut kill_events: EventWriter<KillEvent>,
 ) {
-    for (entity, transform, health) in &entities {
+    for (entity, transform, health, texture, kind) in &entities {
         if health.0 <= 0.0 {
-            let player_transform = player.single();
+            let position = transform.translation.truncate();
+            let target = nearest_tank(&tanks, position).unwrap_or(Vec2::ZERO);
+
+            commands
+                .spawn_bundle(SpriteBundle {
+                    texture: texture.clone(),
+                    transform: *transform,
+                    ..default()
+                })
+                .insert(Dying::new(transform.scale));
+
+            particle_events.send(ParticleEvent {
+                position: transform.translation.truncate(),
+                kind: ParticleKind::Blood,
+                direction: None,
+            });
+
+            kill_events.send(KillEvent {
+                position: transform.translation.truncate(),
+                kind: *kind,
+            });
 
             commands
                 .spawn_bundle(SpriteBundle {
@@ -207,50 +291,54 @@ fn remove_at_zero(
                 .insert(Sensor)
                 .insert(ActiveEvents::COLLISION_EVENTS)
                 .insert(ActiveCollisionTypes::KINEMATIC_STATIC)
-                .insert(Velocity::linear(
-                    (player_transform.translation.truncate() - transform.translation.truncate())
-                        .normalize()
-                        * 500.0,
-                ));
+                .insert(Velocity::linear((target - position).normalize() * 500.0));
 
             commands.entity(entity).despawn_recursive();
         }
     }
 }
 
-fn update_coin_count(coins: Res<Coins>, mut coins_text: Query<&mut Text, With<Coin>>) {
-    if coins.is_changed() {
-        if let Ok(mut text) = coins_text.get_single_mut() {
-            text.sections[0].value = format!("{}", coins.0);
-        }
-    }
+fn tick_survival_time(time: Res<Time>, mut survival_time: ResMut<SurvivalTime>) {
+    survival_time.tick(time.delta());
 }
 
 fn collide_coins(
     mut commands: Commands,
     mut collision_events: EventReader<CollisionEvent>,
     mut coins: ResMut<Coins>,
+    mut score: ResMut<Score>,
+    modifiers: Res<RunModifiers>,
     player: Query<Entity, With<Player>>,
-    coins_q: Query<Entity, With<Coin>>,
+    coins_q: Query<&Transform, With<Coin>>,
     audio: Res<Audio>,
     sound: Res<Sounds>,
+    mut particle_events: EventWriter<ParticleEvent>,
+    mut coin_events: EventWriter<CoinEvent>,
 ) {
     for ev in collision_events.iter() {
         if let CollisionEvent::Started(e1, e2, _) = ev {
-            let (coin_entity, maybe_player) = if let Ok(_) = coins_q.get(*e1) {
-                (e1, e2)
-            } else if let Ok(_) = coins_q.get(*e2) {
-                (e2, e1)
-            } else {
-                continue;
-            };
+            let (coin_entity, coin_transform, maybe_player) =
+                if let Ok(transform) = coins_q.get(*e1) {
+                    (e1, transform, e2)
+                } else if let Ok(transform) = coins_q.get(*e2) {
+                    (e2, transform, e1)
+                } else {
+                    continue;
+                };
 
-            if let Ok(_) = player.get(*maybe_player) {
+            if player.get(*maybe_player).is_ok() {
                 audio.play_with_settings(
                     sound.coin_pickup.clone(),
                     PlaybackSettings::ONCE.with_volume(0.1),
                 );
-                coins.0 += 1;
+                coins.0 += modifiers.effects().coin_value;
+                score.0 += 1;
+                coin_events.send(CoinEvent);
+                particle_events.send(ParticleEvent {
+                    position: coin_transform.translation.truncate(),
+                    kind: ParticleKind::Sparkle,
+                    direction: None,
+                });
                 commands.entity(*coin_entity).despawn_recursive();
             }
         }
@@ -258,11 +346,65 @@ fn collide_coins(
 }
 
 fn spawn_camera(mut commands: Commands) {
-    commands.spawn_bundle(Camera2dBundle::default());
+    let mut camera = Camera2dBundle::default();
+    camera.projection.scaling_mode = ScalingMode::Auto {
+        min_width: ARENA_SIZE.x,
+        min_height: ARENA_SIZE.y,
+    };
+    // the bars are children of the camera so they stay put while it shakes, this puts them at
+    // z 900 in the world
+    let z = 900.0 - camera.transform.translation.z;
+
+    commands
+        .spawn_bundle(camera)
+        .insert_bundle(VisibilityBundle::default())
+        .with_children(|parent| {
+            // black bars covering anything outside the arena when the window's aspect ratio
+            // doesn't match
+            for side in [Vec2::X, Vec2::NEG_X, Vec2::Y, Vec2::NEG_Y] {
+                parent
+                    .spawn_bundle(SpriteBundle {
+                        sprite: Sprite {
+                            color: Color::BLACK,
+                            custom_size: Some(ARENA_SIZE * 4.0),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(
+                            (side * (ARENA_SIZE / 2.0 + ARENA_SIZE * 2.0)).extend(z),
+                        ),
+                        ..default()
+                    })
+                    .insert(Letterbox);
+            }
+        });
 }
 
-fn setup(mut commands: Commands, fonts: Res<Fonts>, pkv: Res<PkvStore>) {
-    let high_score = pkv.get::<u32>("high_score").unwrap_or(0);
+fn toggle_fullscreen(keys: Res<Input<KeyCode>>, mut windows: ResMut<Windows>) {
+    if keys.just_pressed(KeyCode::F11) {
+        let window = windows.primary_mut();
+
+        window.set_mode(if window.mode() == WindowMode::Windowed {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        });
+    }
+}
+
+fn load_settings(mut settings: ResMut<Settings>, pkv: Res<PkvStore>) {
+    if let Ok(saved) = pkv.get::<Settings>("settings") {
+        *settings = saved;
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    fonts: Res<Fonts>,
+    pkv: Res<PkvStore>,
+    settings: Res<Settings>,
+    difficulty: Res<Difficulty>,
+) {
+    let high_score = pkv.get::<u32>(&difficulty.high_score_key()).unwrap_or(0);
     commands
         .spawn_bundle(NodeBundle {
             color: UiColor([0.0; 4].into()),
@@ -291,7 +433,7 @@ fn setup(mut commands: Commands, fonts: Res<Fonts>, pkv: Res<PkvStore>) {
             };
 
             parent.spawn_bundle(TextBundle::from_section(
-                format!("High Score: {}", high_score),
+                format!("High Score ({}): {}", difficulty.preset.name(), high_score),
                 TextStyle {
                     color: Color::YELLOW,
                     font: fonts.main.clone(),
@@ -309,9 +451,198 @@ fn setup(mut commands: Commands, fonts: Res<Fonts>, pkv: Res<PkvStore>) {
                 })
                 .insert(PlayButton);
 
+            if has_saved_run(&pkv) {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(250.0), Val::Px(65.0)),
+                            ..button_style
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(TextBundle::from_section("Continue", text_style.clone()));
+                    })
+                    .insert(ContinueButton);
+            }
+
+            let attempts_left = daily_attempts_left(&pkv);
             parent
                 .spawn_bundle(ButtonBundle {
-                    style: button_style.clone(),
+                    style: Style {
+                        size: Size::new(Val::Px(450.0), Val::Px(65.0)),
+                        ..button_style
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section(
+                        format!("Daily Challenge ({} left)", attempts_left),
+                        TextStyle {
+                            color: if attempts_left > 0 {
+                                Color::WHITE
+                            } else {
+                                Color::GRAY
+                            },
+                            ..text_style.clone()
+                        },
+                    ));
+                })
+                .insert(DailyButton);
+
+            parent.spawn_bundle(TextBundle::from_section(
+                daily_modifiers(today())
+                    .iter()
+                    .map(Modifier::name)
+                    .collect::<Vec<_>>()
+                    .join(" + "),
+                TextStyle {
+                    color: Color::GRAY,
+                    font: fonts.main.clone(),
+                    font_size: 20.0,
+                },
+            ));
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(450.0), Val::Px(65.0)),
+                        ..button_style
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section(
+                        format!("Difficulty: {}", difficulty.preset.name()),
+                        text_style.clone(),
+                    ));
+                })
+                .insert(DifficultyButton);
+
+            // secondary buttons sit side by side to keep the menu on screen
+            parent
+                .spawn_bundle(NodeBundle {
+                    color: UiColor([0.0; 4].into()),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(250.0), Val::Px(65.0)),
+                                ..button_style.clone()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn_bundle(TextBundle::from_section(
+                                "Workshop",
+                                text_style.clone(),
+                            ));
+                        })
+                        .insert(WorkshopButton);
+
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(350.0), Val::Px(65.0)),
+                                ..button_style.clone()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn_bundle(TextBundle::from_section(
+                                "Achievements",
+                                text_style.clone(),
+                            ));
+                        })
+                        .insert(AchievementsButton);
+
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+                                ..button_style.clone()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn_bundle(TextBundle::from_section(
+                                "Stats",
+                                text_style.clone(),
+                            ));
+                        })
+                        .insert(StatsButton);
+                });
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    color: UiColor([0.0; 4].into()),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(250.0), Val::Px(65.0)),
+                                ..button_style.clone()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn_bundle(TextBundle::from_section(
+                                    format!("Shake: {}", settings.screen_shake_label()),
+                                    text_style.clone(),
+                                ))
+                                .insert(ShakeText);
+                        })
+                        .insert(ShakeButton);
+
+                    // there's nowhere to export to on the web
+                    if cfg!(not(target_arch = "wasm32")) {
+                        parent
+                            .spawn_bundle(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(350.0), Val::Px(65.0)),
+                                    ..button_style.clone()
+                                },
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent
+                                    .spawn_bundle(TextBundle::from_section(
+                                        export_label(&settings),
+                                        text_style.clone(),
+                                    ))
+                                    .insert(ExportText);
+                            })
+                            .insert(ExportButton);
+                    }
+
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(250.0), Val::Px(65.0)),
+                                ..button_style.clone()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn_bundle(TextBundle::from_section(
+                                    co_op_label(&settings),
+                                    text_style.clone(),
+                                ))
+                                .insert(CoOpText);
+                        })
+                        .insert(CoOpButton);
+                });
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: button_style,
                     ..default()
                 })
                 .with_children(|parent| {
@@ -323,9 +654,78 @@ fn setup(mut commands: Commands, fonts: Res<Fonts>, pkv: Res<PkvStore>) {
 }
 
 fn play(mut commands: Commands) {
-    commands.insert_resource(NextState(GameState::Playing));
+    commands.insert_resource(NextState(GameState::Mutators));
+}
+
+fn open_difficulty(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Difficulty));
+}
+
+fn open_workshop(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Workshop));
+}
+
+fn open_achievements(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Achievements));
+}
+
+fn open_stats(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Stats));
 }
 
 fn exit(mut ev: EventWriter<AppExit>) {
     ev.send(AppExit);
 }
+
+fn cycle_screen_shake(
+    mut settings: ResMut<Settings>,
+    mut pkv: ResMut<PkvStore>,
+    mut text: Query<&mut Text, With<ShakeText>>,
+) {
+    settings.next_screen_shake();
+    pkv.set("settings", &*settings)
+        .expect("failed to access pkv store");
+
+    for mut text in &mut text {
+        text.sections[0].value = format!("Shake: {}", settings.screen_shake_label());
+    }
+}
+
+fn export_label(settings: &Settings) -> String {
+    format!(
+        "Export stats: {}",
+        if settings.export_stats { "On" } else { "Off" }
+    )
+}
+
+fn toggle_export(
+    mut settings: ResMut<Settings>,
+    mut pkv: ResMut<PkvStore>,
+    mut text: Query<&mut Text, With<ExportText>>,
+) {
+    settings.export_stats = !settings.export_stats;
+    pkv.set("settings", &*settings)
+        .expect("failed to access pkv store");
+
+    for mut text in &mut text {
+        text.sections[0].value = export_label(&settings);
+    }
+}
+
+fn co_op_label(settings: &Settings) -> String {
+    format!("Co-op: {}", if settings.co_op { "On" } else { "Off" })
+}
+
+fn toggle_co_op(
+    mut settings: ResMut<Settings>,
+    mut pkv: ResMut<PkvStore>,
+    mut text: Query<&mut Text, With<CoOpText>>,
+) {
+    settings.co_op = !settings.co_op;
+    pkv.set("settings", &*settings)
+        .expect("failed to access pkv store");
+
+    for mut text in &mut text {
+        text.sections[0].value = co_op_label(&settings);
+    }
+}
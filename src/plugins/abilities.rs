@@ -0,0 +1,252 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+
+use crate::{
+    components::{Coin, Controls, Downed, Enemy, Magnet, Overdrive, Player, Stunned},
+    GameState, ParticleEvent, ParticleKind, ShakeEvent, TankInput, Upgrade, Upgrades,
+};
+
+const SHOCKWAVE_RADIUS: f32 = 350.0;
+const SHOCKWAVE_IMPULSE: f32 = 4000.0;
+const EMP_DURATION: f32 = 3.0;
+const MAGNET_DURATION: f32 = 4.0;
+const MAGNET_SPEED: f32 = 900.0;
+const OVERDRIVE_DURATION: f32 = 5.0;
+// the fire interval is divided by this while overdrive lasts
+pub const OVERDRIVE_SPEEDUP: f32 = 2.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Ability {
+    Shockwave,
+    Emp,
+    Magnet,
+    Overdrive,
+}
+
+impl Ability {
+    fn from_upgrade(upgrade: Upgrade) -> Option<Ability> {
+        match upgrade {
+            Upgrade::Shockwave => Some(Ability::Shockwave),
+            Upgrade::Emp => Some(Ability::Emp),
+            Upgrade::Magnet => Some(Ability::Magnet),
+            Upgrade::Overdrive => Some(Ability::Overdrive),
+            _ => None,
+        }
+    }
+
+    pub fn upgrade(&self) -> Upgrade {
+        match self {
+            Ability::Shockwave => Upgrade::Shockwave,
+            Ability::Emp => Upgrade::Emp,
+            Ability::Magnet => Upgrade::Magnet,
+            Ability::Overdrive => Upgrade::Overdrive,
+        }
+    }
+
+    // in seconds
+    fn cooldown(&self) -> f32 {
+        match self {
+            Ability::Shockwave => 8.0,
+            Ability::Emp => 30.0,
+            Ability::Magnet => 15.0,
+            Ability::Overdrive => 20.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AbilitySlot {
+    pub ability: Ability,
+    pub cooldown: Timer,
+}
+
+impl AbilitySlot {
+    // a freshly bought ability can be used right away
+    fn new(ability: Ability) -> AbilitySlot {
+        let mut cooldown = Timer::from_seconds(ability.cooldown(), false);
+        cooldown.tick(Duration::from_secs_f32(ability.cooldown()));
+
+        AbilitySlot { ability, cooldown }
+    }
+}
+
+// the abilities of a tank in the order they were bought, every slot has its own key or button
+#[derive(Component, Default)]
+pub struct AbilitySlots(pub Vec<AbilitySlot>);
+
+impl AbilitySlots {
+    // cooldowns of abilities that are still owned carry over, respecs included
+    pub fn sync(&mut self, upgrades: &Upgrades) {
+        let mut old = std::mem::take(&mut self.0);
+
+        for ability in upgrades
+            .0
+            .iter()
+            .filter_map(|upgrade| Ability::from_upgrade(*upgrade))
+        {
+            let slot = match old.iter().position(|slot| slot.ability == ability) {
+                Some(index) => old.swap_remove(index),
+                None => AbilitySlot::new(ability),
+            };
+            self.0.push(slot);
+        }
+    }
+}
+
+// the key or button of a slot, as shown in the HUD
+pub fn slot_label(controls: Controls, slot: usize) -> &'static str {
+    match controls {
+        Controls::Mouse => ["Q", "E", "R", "F"][slot],
+        Controls::Gamepad(_) => ["A", "B", "LB", "RB"][slot],
+    }
+}
+
+pub struct AbilityPlugin;
+
+impl Plugin for AbilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(sync_ability_slots).add_system_set(
+            ConditionSet::new()
+                .run_in_state(GameState::Playing)
+                .with_system(tick_cooldowns)
+                .with_system(use_abilities)
+                .with_system(wear_off_stuns)
+                .with_system(wear_off_overdrive)
+                .with_system(pull_coins)
+                .into(),
+        );
+    }
+}
+
+fn sync_ability_slots(mut tanks: Query<(&Upgrades, &mut AbilitySlots), Changed<Upgrades>>) {
+    for (upgrades, mut slots) in &mut tanks {
+        slots.sync(upgrades);
+    }
+}
+
+fn tick_cooldowns(time: Res<Time>, mut tanks: Query<&mut AbilitySlots>) {
+    for mut slots in &mut tanks {
+        for slot in &mut slots.0 {
+            slot.cooldown.tick(time.delta());
+        }
+    }
+}
+
+fn use_abilities(
+    mut commands: Commands,
+    input: TankInput,
+    mut tanks: Query<
+        (Entity, &Transform, &Controls, &mut AbilitySlots),
+        (With<Player>, Without<Downed>),
+    >,
+    mut enemies: Query<(Entity, &Transform, &mut ExternalImpulse), (With<Enemy>, Without<Player>)>,
+    mut particle_events: EventWriter<ParticleEvent>,
+    mut shake_events: EventWriter<ShakeEvent>,
+) {
+    for (tank, transform, controls, mut slots) in &mut tanks {
+        let position = transform.translation.truncate();
+
+        for (index, slot) in slots.0.iter_mut().enumerate() {
+            if !slot.cooldown.finished() || !input.ability_just_pressed(*controls, index) {
+                continue;
+            }
+
+            slot.cooldown.reset();
+
+            match slot.ability {
+                Ability::Shockwave => {
+                    for (_, enemy, mut impulse) in &mut enemies {
+                        let away = enemy.translation.truncate() - position;
+                        let distance = away.length();
+
+                        // the closer a zombie is, the further it flies
+                        if distance <= SHOCKWAVE_RADIUS {
+                            impulse.impulse = away.normalize_or_zero()
+                                * SHOCKWAVE_IMPULSE
+                                * (1.0 - distance / SHOCKWAVE_RADIUS);
+                        }
+                    }
+
+                    particle_events.send(ParticleEvent {
+                        position,
+                        kind: ParticleKind::Sparks,
+                        direction: None,
+                    });
+                    shake_events.send(ShakeEvent(0.5));
+                }
+                Ability::Emp => {
+                    for (enemy, enemy_transform, _) in &enemies {
+                        commands
+                            .entity(enemy)
+                            .insert(Stunned(Timer::from_seconds(EMP_DURATION, false)));
+
+                        particle_events.send(ParticleEvent {
+                            position: enemy_transform.translation.truncate(),
+                            kind: ParticleKind::Sparkle,
+                            direction: None,
+                        });
+                    }
+                }
+                Ability::Magnet => {
+                    commands
+                        .entity(tank)
+                        .insert(Magnet(Timer::from_seconds(MAGNET_DURATION, false)));
+                }
+                Ability::Overdrive => {
+                    commands
+                        .entity(tank)
+                        .insert(Overdrive(Timer::from_seconds(OVERDRIVE_DURATION, false)));
+                }
+            }
+        }
+    }
+}
+
+fn wear_off_stuns(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut enemies: Query<(Entity, &mut Stunned)>,
+) {
+    for (entity, mut stunned) in &mut enemies {
+        stunned.tick(time.delta());
+        if stunned.finished() {
+            commands.entity(entity).remove::<Stunned>();
+        }
+    }
+}
+
+fn wear_off_overdrive(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut tanks: Query<(Entity, &mut Overdrive)>,
+) {
+    for (entity, mut overdrive) in &mut tanks {
+        overdrive.tick(time.delta());
+        if overdrive.finished() {
+            commands.entity(entity).remove::<Overdrive>();
+        }
+    }
+}
+
+fn pull_coins(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut tanks: Query<(Entity, &Transform, &mut Magnet)>,
+    mut coins: Query<(&Transform, &mut Velocity), (With<Coin>, Without<Magnet>)>,
+) {
+    for (entity, transform, mut magnet) in &mut tanks {
+        magnet.tick(time.delta());
+        if magnet.finished() {
+            commands.entity(entity).remove::<Magnet>();
+            continue;
+        }
+
+        for (coin, mut velocity) in &mut coins {
+            let to_tank = transform.translation.truncate() - coin.translation.truncate();
+            velocity.linvel = to_tank.normalize_or_zero() * MAGNET_SPEED;
+        }
+    }
+}
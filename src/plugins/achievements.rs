@@ -0,0 +1,349 @@
+use bevy::prelude::*;
+use bevy_pkv::PkvStore;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    button_pressed, despawn_with,
+    resources::{Fonts, SurvivalTime},
+    update_buttons, DamageEvent, DamageKind, EnemyKind, GameState, Upgrades,
+};
+
+// sent whenever an enemy dies
+pub struct KillEvent {
+    pub position: Vec2,
+    pub kind: EnemyKind,
+}
+
+// sent whenever the player picks up a coin
+pub struct CoinEvent;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Achievement {
+    FirstBlood,
+    Exterminator,
+    PiggyBank,
+    Shopaholic,
+    FullyLoaded,
+    Survivor,
+    Untouchable,
+}
+
+impl Achievement {
+    const ALL: [Achievement; 7] = [
+        Achievement::FirstBlood,
+        Achievement::Exterminator,
+        Achievement::PiggyBank,
+        Achievement::Shopaholic,
+        Achievement::FullyLoaded,
+        Achievement::Survivor,
+        Achievement::Untouchable,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Achievement::FirstBlood => "First Blood",
+            Achievement::Exterminator => "Exterminator",
+            Achievement::PiggyBank => "Piggy Bank",
+            Achievement::Shopaholic => "Shopaholic",
+            Achievement::FullyLoaded => "Fully Loaded",
+            Achievement::Survivor => "Survivor",
+            Achievement::Untouchable => "Untouchable",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Achievement::FirstBlood => "Kill a zombie",
+            Achievement::Exterminator => "Kill 500 zombies in one run",
+            Achievement::PiggyBank => "Collect 100 coins in one run",
+            Achievement::Shopaholic => "Buy 6 upgrades in one run",
+            Achievement::FullyLoaded => "Buy 12 upgrades in one run",
+            Achievement::Survivor => "Survive for 5 minutes",
+            Achievement::Untouchable => "Go a whole minute without getting hit",
+        }
+    }
+
+    fn reached(&self, progress: &RunProgress, survival_time: f32) -> bool {
+        match self {
+            Achievement::FirstBlood => progress.kills >= 1,
+            Achievement::Exterminator => progress.kills >= 500,
+            Achievement::PiggyBank => progress.coins >= 100,
+            Achievement::Shopaholic => progress.upgrades >= 6,
+            Achievement::FullyLoaded => progress.upgrades >= 12,
+            Achievement::Survivor => survival_time >= 300.0,
+            Achievement::Untouchable => progress.since_hit >= 60.0,
+        }
+    }
+}
+
+// every achievement unlocked so far, saved under "achievements"
+#[derive(Default, Serialize, Deserialize)]
+pub struct Achievements(pub Vec<Achievement>);
+
+// counters for the current run, saved along with it
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct RunProgress {
+    kills: u32,
+    coins: u32,
+    upgrades: u32,
+    // seconds played since the player was last hit
+    since_hit: f32,
+}
+
+#[derive(Component)]
+struct Toast(Timer);
+
+#[derive(Component)]
+struct AchievementsMenu;
+
+#[derive(Component)]
+struct BackButton;
+
+pub struct AchievementsPlugin;
+
+impl Plugin for AchievementsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<KillEvent>()
+            .add_event::<CoinEvent>()
+            .init_resource::<Achievements>()
+            .init_resource::<RunProgress>()
+            .add_startup_system(load_achievements)
+            .add_enter_system(GameState::Menu, reset_progress)
+            .add_enter_system(GameState::Menu, despawn_with::<Toast>)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_not_in_state(GameState::Loading)
+                    .run_not_in_state(GameState::Menu)
+                    .with_system(track_progress)
+                    .with_system(unlock_achievements)
+                    .with_system(update_toasts)
+                    .into(),
+            )
+            .add_system(tick_since_hit.run_in_state(GameState::Playing))
+            .add_enter_system(GameState::Achievements, spawn_gallery)
+            .add_exit_system(GameState::Achievements, despawn_with::<AchievementsMenu>)
+            .add_system(update_buttons.run_in_state(GameState::Achievements))
+            .add_system(
+                to_menu
+                    .run_in_state(GameState::Achievements)
+                    .run_if(button_pressed::<BackButton>),
+            );
+    }
+}
+
+fn load_achievements(mut achievements: ResMut<Achievements>, pkv: Res<PkvStore>) {
+    if let Ok(saved) = pkv.get::<Achievements>("achievements") {
+        *achievements = saved;
+    }
+}
+
+fn reset_progress(mut progress: ResMut<RunProgress>) {
+    *progress = default();
+}
+
+fn tick_since_hit(time: Res<Time>, mut progress: ResMut<RunProgress>) {
+    progress.since_hit += time.delta_seconds();
+}
+
+fn track_progress(
+    mut progress: ResMut<RunProgress>,
+    mut kill_events: EventReader<KillEvent>,
+    mut coin_events: EventReader<CoinEvent>,
+    tanks: Query<&Upgrades>,
+    mut damage_events: EventReader<DamageEvent>,
+) {
+    progress.kills += kill_events.iter().count() as u32;
+    progress.coins += coin_events.iter().count() as u32;
+
+    // the most upgrades held at once, so buying them back after a respec doesn't count twice
+    let held = tanks.iter().map(|upgrades| upgrades.0.len() as u32).sum();
+    progress.upgrades = progress.upgrades.max(held);
+
+    if damage_events
+        .iter()
+        .any(|ev| matches!(ev.kind, DamageKind::Player))
+    {
+        progress.since_hit = 0.0;
+    }
+}
+
+fn unlock_achievements(
+    mut commands: Commands,
+    fonts: Res<Fonts>,
+    progress: Res<RunProgress>,
+    survival_time: Res<SurvivalTime>,
+    mut achievements: ResMut<Achievements>,
+    mut pkv: ResMut<PkvStore>,
+    toasts: Query<(), With<Toast>>,
+) {
+    let mut shown = toasts.iter().count();
+
+    for achievement in Achievement::ALL {
+        if achievements.0.contains(&achievement)
+            || !achievement.reached(&progress, survival_time.elapsed_secs())
+        {
+            continue;
+        }
+
+        achievements.0.push(achievement);
+        pkv.set("achievements", &*achievements)
+            .expect("failed to access pkv store");
+
+        // stack toasts below each other when several unlock at once
+        commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor([0.0; 4].into()),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Px(80.0 + 90.0 * shown as f32),
+                        ..default()
+                    },
+                    size: Size::new(Val::Percent(100.0), Val::Auto),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            })
+            .insert(Toast(Timer::from_seconds(3.0, false)))
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        color: UiColor(Color::rgba(0.0, 0.0, 0.0, 0.85)),
+                        style: Style {
+                            flex_direction: FlexDirection::ColumnReverse,
+                            align_items: AlignItems::Center,
+                            padding: UiRect::all(Val::Px(10.0)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle::from_section(
+                            format!("Achievement: {}", achievement.name()),
+                            TextStyle {
+                                color: Color::YELLOW,
+                                font: fonts.main.clone(),
+                                font_size: 30.0,
+                            },
+                        ));
+                        parent.spawn_bundle(TextBundle::from_section(
+                            achievement.description(),
+                            TextStyle {
+                                color: Color::WHITE,
+                                font: fonts.main.clone(),
+                                font_size: 20.0,
+                            },
+                        ));
+                    });
+            });
+
+        shown += 1;
+    }
+}
+
+fn update_toasts(mut commands: Commands, time: Res<Time>, mut toasts: Query<(Entity, &mut Toast)>) {
+    for (entity, mut toast) in &mut toasts {
+        toast.0.tick(time.delta());
+
+        if toast.0.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn spawn_gallery(mut commands: Commands, fonts: Res<Fonts>, achievements: Res<Achievements>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            color: UiColor([0.0; 4].into()),
+            style: Style {
+                margin: UiRect::all(Val::Auto),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(AchievementsMenu)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                format!(
+                    "Achievements: {}/{}",
+                    achievements.0.len(),
+                    Achievement::ALL.len()
+                ),
+                TextStyle {
+                    color: Color::YELLOW,
+                    font: fonts.main.clone(),
+                    font_size: 50.0,
+                },
+            ));
+
+            for achievement in Achievement::ALL {
+                let unlocked = achievements.0.contains(&achievement);
+
+                parent
+                    .spawn_bundle(NodeBundle {
+                        color: UiColor([0.0; 4].into()),
+                        style: Style {
+                            margin: UiRect::all(Val::Px(6.0)),
+                            flex_direction: FlexDirection::ColumnReverse,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle::from_section(
+                            achievement.name(),
+                            TextStyle {
+                                color: if unlocked { Color::WHITE } else { Color::GRAY },
+                                font: fonts.main.clone(),
+                                font_size: 30.0,
+                            },
+                        ));
+                        parent.spawn_bundle(TextBundle::from_section(
+                            achievement.description(),
+                            TextStyle {
+                                color: if unlocked {
+                                    Color::GREEN
+                                } else {
+                                    Color::DARK_GRAY
+                                },
+                                font: fonts.main.clone(),
+                                font_size: 20.0,
+                            },
+                        ));
+                    });
+            }
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+                        margin: UiRect::all(Val::Px(6.0)),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(BackButton)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section(
+                        "Back",
+                        TextStyle {
+                            color: Color::WHITE,
+                            font: fonts.main.clone(),
+                            font_size: 35.0,
+                        },
+                    ));
+                });
+        });
+}
+
+fn to_menu(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Menu));
+}
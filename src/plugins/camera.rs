@@ -0,0 +1,59 @@
+use bevy::prelude::*;
+use rand::{thread_rng, Rng};
+
+use crate::resources::Settings;
+
+const MAX_OFFSET: f32 = 30.0;
+const MAX_ANGLE: f32 = 0.05;
+// trauma lost per second
+const DECAY: f32 = 1.5;
+
+// adds this much trauma, which is capped at 1.0
+pub struct ShakeEvent(pub f32);
+
+#[derive(Default)]
+pub struct Trauma(pub f32);
+
+pub struct CameraShakePlugin;
+
+impl Plugin for CameraShakePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ShakeEvent>()
+            .init_resource::<Trauma>()
+            .add_system(add_trauma)
+            .add_system(shake_camera.after(add_trauma));
+    }
+}
+
+fn add_trauma(mut events: EventReader<ShakeEvent>, mut trauma: ResMut<Trauma>) {
+    for ev in events.iter() {
+        trauma.0 = (trauma.0 + ev.0).min(1.0);
+    }
+}
+
+fn shake_camera(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut trauma: ResMut<Trauma>,
+    mut camera: Query<&mut Transform, With<Camera>>,
+) {
+    trauma.0 = (trauma.0 - DECAY * time.delta_seconds()).max(0.0);
+
+    // squaring makes small amounts of trauma barely noticeable while big hits still kick
+    let shake = trauma.0 * trauma.0 * settings.screen_shake;
+
+    let mut rng = thread_rng();
+
+    for mut transform in &mut camera {
+        if shake <= 0.0 {
+            transform.translation.x = 0.0;
+            transform.translation.y = 0.0;
+            transform.rotation = Quat::IDENTITY;
+            continue;
+        }
+
+        transform.translation.x = MAX_OFFSET * shake * rng.gen_range(-1.0..1.0);
+        transform.translation.y = MAX_OFFSET * shake * rng.gen_range(-1.0..1.0);
+        transform.rotation = Quat::from_rotation_z(MAX_ANGLE * shake * rng.gen_range(-1.0..1.0));
+    }
+}
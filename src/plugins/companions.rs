@@ -0,0 +1,367 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+
+use crate::{
+    components::{
+        Aim, Barrel, Controls, Damage, Downed, Enemy, Health, Loadout, Owner, Player, ShootTimer,
+        Stunned, WeaponBundle,
+    },
+    despawn_with, point_barrel,
+    resources::{MousePosition, Sprites, ARENA_SIZE},
+    spawn_bullet, GameState, ParticleEvent, ParticleKind, RunModifiers, ShotEvent, TankInput,
+    Upgrade, Upgrades,
+};
+
+const TURRET_RANGE: f32 = 500.0;
+const TURRET_HEALTH: f32 = 150.0;
+const DRONE_RANGE: f32 = 400.0;
+const DRONE_HEALTH: f32 = 80.0;
+const DRONE_ORBIT: f32 = 90.0;
+// in radians per second
+const DRONE_SPEED: f32 = 2.0;
+// how close a zombie has to get to chew on a companion
+const COMPANION_REACH: f32 = 60.0;
+
+// shoots zombies on its own, it belongs to the tank in its `Owner`
+#[derive(Component)]
+pub struct Companion;
+
+// placing more turrets than allowed takes away the oldest ones
+#[derive(Component)]
+pub struct Turret {
+    pub placed_at: f64,
+}
+
+#[derive(Component)]
+struct Drone {
+    angle: f32,
+}
+
+pub struct CompanionPlugin;
+
+impl Plugin for CompanionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            ConditionSet::new()
+                .run_in_state(GameState::Playing)
+                .with_system(place_turrets)
+                .with_system(sync_companions)
+                .with_system(orbit_drones)
+                .with_system(fire_companions)
+                .with_system(damage_companions)
+                .into(),
+        )
+        .add_enter_system(GameState::GameOver, despawn_with::<Companion>)
+        .add_enter_system(GameState::Menu, despawn_with::<Companion>);
+    }
+}
+
+fn max_turrets(upgrades: &Upgrades) -> usize {
+    if upgrades.0.contains(&Upgrade::TurretNest) {
+        2
+    } else if upgrades.0.contains(&Upgrade::Turret) {
+        1
+    } else {
+        0
+    }
+}
+
+fn drone_count(upgrades: &Upgrades) -> usize {
+    if upgrades.0.contains(&Upgrade::DroneSwarm) {
+        2
+    } else if upgrades.0.contains(&Upgrade::Drone) {
+        1
+    } else {
+        0
+    }
+}
+
+// companions only care about their own branch of the tree, not the tank's loadout
+fn companion_loadout(damage: f32, fire_interval: f32, modifiers: &RunModifiers) -> Loadout {
+    Loadout {
+        damage: Damage(damage * modifiers.effects().damage),
+        fire_interval,
+        ..default()
+    }
+}
+
+pub fn turret_loadout(upgrades: &Upgrades, modifiers: &RunModifiers) -> Loadout {
+    if upgrades.0.contains(&Upgrade::TurretNest) {
+        companion_loadout(40.0, 0.3, modifiers)
+    } else {
+        companion_loadout(40.0, 0.45, modifiers)
+    }
+}
+
+fn drone_loadout(upgrades: &Upgrades, modifiers: &RunModifiers) -> Loadout {
+    if upgrades.0.contains(&Upgrade::DroneSwarm) {
+        companion_loadout(35.0, 0.5, modifiers)
+    } else {
+        companion_loadout(25.0, 0.5, modifiers)
+    }
+}
+
+fn spawn_companion(
+    commands: &mut Commands,
+    texture: Handle<Image>,
+    transform: Transform,
+    owner: Entity,
+    health: f32,
+    loadout: Loadout,
+) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture,
+            transform,
+            ..default()
+        })
+        .insert(Companion)
+        .insert(Owner(owner))
+        .insert(Health::new(health))
+        .insert_bundle(WeaponBundle::new(loadout))
+        .id()
+}
+
+// turned like the tanks so barrels point the same way on both
+pub fn spawn_turret(
+    commands: &mut Commands,
+    sprites: &Sprites,
+    position: Vec2,
+    owner: Entity,
+    loadout: Loadout,
+    placed_at: f64,
+) -> Entity {
+    let turret = spawn_companion(
+        commands,
+        sprites.base.clone(),
+        Transform::from_translation(position.extend(0.5))
+            .with_scale(Vec3::splat(3.5))
+            .with_rotation(Quat::from_rotation_z(PI / 2.0)),
+        owner,
+        TURRET_HEALTH,
+        loadout,
+    );
+
+    commands
+        .entity(turret)
+        .insert(Turret { placed_at })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(SpriteSheetBundle {
+                    texture_atlas: sprites.barrel.clone(),
+                    transform: Transform::from_translation(Vec3::Z * 10.0),
+                    ..default()
+                })
+                .insert(Barrel);
+        });
+
+    turret
+}
+
+fn place_turrets(
+    mut commands: Commands,
+    time: Res<Time>,
+    input: TankInput,
+    mouse_pos: Res<MousePosition>,
+    sprites: Res<Sprites>,
+    modifiers: Res<RunModifiers>,
+    tanks: Query<(Entity, &Transform, &Controls, &Aim, &Upgrades), (With<Player>, Without<Downed>)>,
+    turrets: Query<(Entity, &Owner, &Turret)>,
+) {
+    for (tank, transform, controls, aim, upgrades) in &tanks {
+        let max = max_turrets(upgrades);
+        if max == 0 || !input.place_turret(*controls) {
+            continue;
+        }
+
+        // gamepads have no cursor, so their turrets go a bit in front of the tank
+        let position = match controls {
+            Controls::Mouse => mouse_pos.0,
+            Controls::Gamepad(_) => transform.translation.truncate() + aim.0 * 150.0,
+        }
+        .clamp(-ARENA_SIZE / 2.0, ARENA_SIZE / 2.0);
+
+        let mut placed: Vec<_> = turrets
+            .iter()
+            .filter(|(_, owner, _)| owner.0 == tank)
+            .collect();
+        placed.sort_by(|(_, _, a), (_, _, b)| a.placed_at.total_cmp(&b.placed_at));
+        for (entity, ..) in placed.iter().take((placed.len() + 1).saturating_sub(max)) {
+            commands.entity(*entity).despawn_recursive();
+        }
+
+        spawn_turret(
+            &mut commands,
+            &sprites,
+            position,
+            tank,
+            turret_loadout(upgrades, &modifiers),
+            time.seconds_since_startup(),
+        );
+    }
+}
+
+// drones are rebuilt from the upgrades, so buying anything also replaces destroyed ones,
+// turrets only get better or go away on a respec
+fn sync_companions(
+    mut commands: Commands,
+    sprites: Res<Sprites>,
+    modifiers: Res<RunModifiers>,
+    tanks: Query<(Entity, &Transform, &Upgrades), Changed<Upgrades>>,
+    drones: Query<(Entity, &Owner), With<Drone>>,
+    mut turrets: Query<(Entity, &Owner, &Turret, &mut Loadout)>,
+) {
+    for (tank, transform, upgrades) in &tanks {
+        for (entity, owner) in &drones {
+            if owner.0 == tank {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+
+        let count = drone_count(upgrades);
+        for index in 0..count {
+            let angle = TAU * index as f32 / count as f32;
+            let position = transform.translation.truncate() + Vec2::from_angle(angle) * DRONE_ORBIT;
+
+            let drone = spawn_companion(
+                &mut commands,
+                sprites.drone.clone(),
+                Transform::from_translation(position.extend(0.5)).with_scale(Vec3::splat(3.0)),
+                tank,
+                DRONE_HEALTH,
+                drone_loadout(upgrades, &modifiers),
+            );
+            commands.entity(drone).insert(Drone { angle });
+        }
+
+        let mut placed: Vec<_> = turrets
+            .iter_mut()
+            .filter(|(_, owner, ..)| owner.0 == tank)
+            .collect();
+        placed.sort_by(|(_, _, a, _), (_, _, b, _)| b.placed_at.total_cmp(&a.placed_at));
+        for (index, (entity, _, _, mut loadout)) in placed.into_iter().enumerate() {
+            if index < max_turrets(upgrades) {
+                *loadout = turret_loadout(upgrades, &modifiers);
+            } else {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn orbit_drones(
+    time: Res<Time>,
+    tanks: Query<&Transform, (With<Player>, Without<Drone>)>,
+    mut drones: Query<(&mut Transform, &mut Drone, &Owner)>,
+) {
+    for (mut transform, mut drone, owner) in &mut drones {
+        drone.angle += DRONE_SPEED * time.delta_seconds();
+
+        if let Ok(tank) = tanks.get(owner.0) {
+            let position =
+                tank.translation.truncate() + Vec2::from_angle(drone.angle) * DRONE_ORBIT;
+            transform.translation = position.extend(transform.translation.z);
+        }
+    }
+}
+
+fn fire_companions(
+    mut commands: Commands,
+    time: Res<Time>,
+    sprites: Res<Sprites>,
+    mut companions: Query<
+        (
+            Entity,
+            &Transform,
+            &Loadout,
+            &mut ShootTimer,
+            &Owner,
+            Option<&Children>,
+            Option<&Drone>,
+        ),
+        With<Companion>,
+    >,
+    downed: Query<(), With<Downed>>,
+    mut barrels: Query<
+        (&mut Transform, &mut TextureAtlasSprite),
+        (With<Barrel>, Without<Companion>),
+    >,
+    enemies: Query<&Transform, (With<Enemy>, Without<Companion>, Without<Barrel>)>,
+    mut shot_events: EventWriter<ShotEvent>,
+) {
+    for (entity, transform, loadout, mut timer, owner, children, drone) in &mut companions {
+        // companions give up along with their tank
+        if downed.contains(owner.0) {
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        let range = if drone.is_some() {
+            DRONE_RANGE
+        } else {
+            TURRET_RANGE
+        };
+
+        let target = enemies
+            .iter()
+            .map(|enemy| enemy.translation.truncate() - position)
+            .filter(|to_enemy| to_enemy.length() <= range)
+            .min_by(|a, b| a.length().total_cmp(&b.length()));
+
+        // without anything in range the timer waits, so the first shot isn't instant
+        let dir = if let Some(to_enemy) = target {
+            to_enemy.normalize_or_zero()
+        } else {
+            continue;
+        };
+
+        for child in children.into_iter().flatten() {
+            if let Ok((mut transform, mut sprite)) = barrels.get_mut(*child) {
+                point_barrel(&mut transform, &mut sprite, dir);
+            }
+        }
+
+        timer.tick(time.delta());
+        if timer.just_finished() {
+            spawn_bullet(
+                &mut commands,
+                &sprites,
+                loadout,
+                entity,
+                transform.translation,
+                dir,
+            );
+            shot_events.send(ShotEvent);
+        }
+    }
+}
+
+// zombies chew on any companion they bump into, without the windup they have against tanks
+fn damage_companions(
+    mut commands: Commands,
+    time: Res<Time>,
+    enemies: Query<(&Transform, &Damage), (With<Enemy>, Without<Stunned>)>,
+    mut companions: Query<(Entity, &Transform, &mut Health), (With<Companion>, Without<Enemy>)>,
+    mut particle_events: EventWriter<ParticleEvent>,
+) {
+    for (entity, transform, mut health) in &mut companions {
+        let position = transform.translation.truncate();
+
+        for (enemy, damage) in &enemies {
+            if enemy.translation.truncate().distance(position) <= COMPANION_REACH {
+                health.0 -= damage.0 * time.delta_seconds();
+            }
+        }
+
+        if health.0 <= 0.0 {
+            particle_events.send(ParticleEvent {
+                position,
+                kind: ParticleKind::Sparks,
+                direction: None,
+            });
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
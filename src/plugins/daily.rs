@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+use bevy_pkv::PkvStore;
+use iyes_loopless::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    button_pressed, resources::Difficulty, DailyButton, GameState, Modifier, RunModifiers,
+};
+
+const ATTEMPTS_PER_DAY: u32 = 3;
+const MODIFIERS_PER_DAY: usize = 2;
+
+// today's attempts and best score, saved under "daily"
+#[derive(Default, Serialize, Deserialize)]
+struct DailyRecord {
+    day: u64,
+    attempts: u32,
+    best: u32,
+}
+
+pub struct DailyPlugin;
+
+impl Plugin for DailyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            start_daily
+                .run_in_state(GameState::Menu)
+                .run_if(button_pressed::<DailyButton>),
+        );
+    }
+}
+
+// days since the unix epoch, so everyone plays the same challenge on the same (utc) day
+#[cfg(not(target_arch = "wasm32"))]
+pub fn today() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() / 86400)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn today() -> u64 {
+    (js_sys::Date::now() / 86_400_000.0) as u64
+}
+
+// the record for the given day, an older one counts as no attempts yet
+fn daily_record(pkv: &PkvStore, day: u64) -> DailyRecord {
+    match pkv.get::<DailyRecord>("daily") {
+        Ok(record) if record.day == day => record,
+        _ => DailyRecord { day, ..default() },
+    }
+}
+
+pub fn daily_modifiers(day: u64) -> Vec<Modifier> {
+    let mut rng = StdRng::seed_from_u64(day);
+    Modifier::ALL
+        .choose_multiple(&mut rng, MODIFIERS_PER_DAY)
+        .copied()
+        .collect()
+}
+
+pub fn daily_attempts_left(pkv: &PkvStore) -> u32 {
+    ATTEMPTS_PER_DAY.saturating_sub(daily_record(pkv, today()).attempts)
+}
+
+// returns the best score of the day the run was started on
+pub fn record_daily_score(pkv: &mut PkvStore, day: u64, score: u32) -> u32 {
+    let mut record = daily_record(pkv, day);
+    record.best = record.best.max(score);
+    pkv.set("daily", &record)
+        .expect("failed to access pkv store");
+
+    record.best
+}
+
+fn start_daily(
+    mut commands: Commands,
+    mut modifiers: ResMut<RunModifiers>,
+    mut difficulty: ResMut<Difficulty>,
+    mut pkv: ResMut<PkvStore>,
+) {
+    let day = today();
+    let mut record = daily_record(&pkv, day);
+    if record.attempts >= ATTEMPTS_PER_DAY {
+        return;
+    }
+
+    record.attempts += 1;
+    pkv.set("daily", &record)
+        .expect("failed to access pkv store");
+
+    *modifiers = RunModifiers {
+        modifiers: daily_modifiers(day),
+        seed: Some(day),
+        daily: true,
+    };
+    // everyone plays the daily on normal, whatever they picked for their own runs
+    *difficulty = Difficulty::default();
+    commands.insert_resource(NextState(GameState::Playing));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn everyone_gets_the_same_modifiers_on_the_same_day() {
+        assert!(daily_modifiers(19_000) == daily_modifiers(19_000));
+    }
+
+    #[test]
+    fn a_day_has_distinct_modifiers() {
+        for day in 0..100 {
+            let modifiers = daily_modifiers(day);
+            assert_eq!(modifiers.len(), MODIFIERS_PER_DAY);
+            assert!(modifiers[0] != modifiers[1]);
+        }
+    }
+
+    #[test]
+    fn the_modifiers_rotate() {
+        let first = daily_modifiers(0);
+        assert!((1..30).any(|day| daily_modifiers(day) != first));
+    }
+}
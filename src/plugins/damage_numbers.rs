@@ -0,0 +1,153 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use rand::{thread_rng, Rng};
+
+use crate::{resources::Fonts, GameState};
+
+// there's never more than this many numbers on screen, the oldest one gets reused first
+const POOL_SIZE: usize = 64;
+
+#[derive(Clone, Copy)]
+pub enum DamageKind {
+    Normal,
+    Crit,
+    Explosion,
+    Player,
+}
+
+impl DamageKind {
+    fn color(&self) -> Color {
+        match self {
+            DamageKind::Normal => Color::WHITE,
+            DamageKind::Crit => Color::YELLOW,
+            DamageKind::Explosion => Color::ORANGE,
+            DamageKind::Player => Color::RED,
+        }
+    }
+
+    fn font_size(&self) -> f32 {
+        match self {
+            DamageKind::Crit => 40.0,
+            _ => 28.0,
+        }
+    }
+}
+
+pub struct DamageEvent {
+    pub position: Vec2,
+    pub amount: f32,
+    pub kind: DamageKind,
+}
+
+#[derive(Component)]
+struct DamageNumber(Timer);
+
+#[derive(Default)]
+struct DamageNumberPool {
+    entities: Vec<Entity>,
+    next: usize,
+}
+
+pub struct DamageNumberPlugin;
+
+impl Plugin for DamageNumberPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>()
+            .init_resource::<DamageNumberPool>()
+            .add_exit_system(GameState::Loading, spawn_pool)
+            .add_enter_system(GameState::GameOver, hide_damage_numbers)
+            .add_enter_system(GameState::Menu, hide_damage_numbers)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Playing)
+                    .with_system(show_damage_numbers)
+                    .with_system(animate_damage_numbers)
+                    .into(),
+            );
+    }
+}
+
+fn spawn_pool(mut commands: Commands, fonts: Res<Fonts>, mut pool: ResMut<DamageNumberPool>) {
+    for _ in 0..POOL_SIZE {
+        let entity = commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::from_section(
+                    "",
+                    TextStyle {
+                        font: fonts.main.clone(),
+                        font_size: 28.0,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_alignment(TextAlignment::CENTER),
+                visibility: Visibility { is_visible: false },
+                ..default()
+            })
+            .insert(DamageNumber(Timer::from_seconds(0.6, false)))
+            .id();
+
+        pool.entities.push(entity);
+    }
+}
+
+fn show_damage_numbers(
+    mut events: EventReader<DamageEvent>,
+    mut pool: ResMut<DamageNumberPool>,
+    mut numbers: Query<(
+        &mut Text,
+        &mut Transform,
+        &mut Visibility,
+        &mut DamageNumber,
+    )>,
+) {
+    let mut rng = thread_rng();
+
+    for ev in events.iter() {
+        let entity = pool.entities[pool.next];
+        pool.next = (pool.next + 1) % pool.entities.len();
+
+        if let Ok((mut text, mut transform, mut visibility, mut number)) = numbers.get_mut(entity) {
+            text.sections[0].value = format!("{:.0}", ev.amount);
+            text.sections[0].style.color = ev.kind.color();
+            text.sections[0].style.font_size = ev.kind.font_size();
+
+            // a little jitter so numbers from the same spot don't stack perfectly
+            let offset = Vec2::new(rng.gen_range(-15.0..15.0), rng.gen_range(-5.0..5.0));
+            transform.translation = (ev.position + offset).extend(50.0);
+
+            visibility.is_visible = true;
+            number.0.reset();
+        }
+    }
+}
+
+fn animate_damage_numbers(
+    time: Res<Time>,
+    mut numbers: Query<(
+        &mut Text,
+        &mut Transform,
+        &mut Visibility,
+        &mut DamageNumber,
+    )>,
+) {
+    for (mut text, mut transform, mut visibility, mut number) in &mut numbers {
+        if !visibility.is_visible {
+            continue;
+        }
+
+        number.0.tick(time.delta());
+
+        transform.translation.y += 80.0 * time.delta_seconds();
+        text.sections[0].style.color.set_a(1.0 - number.0.percent());
+
+        if number.0.finished() {
+            visibility.is_visible = false;
+        }
+    }
+}
+
+fn hide_damage_numbers(mut numbers: Query<&mut Visibility, With<DamageNumber>>) {
+    for mut visibility in &mut numbers {
+        visibility.is_visible = false;
+    }
+}
@@ -0,0 +1,431 @@
+use bevy::prelude::*;
+use bevy_pkv::PkvStore;
+use iyes_loopless::prelude::*;
+
+use crate::{
+    button_pressed, despawn_with,
+    resources::{Difficulty, DifficultyPreset, Fonts},
+    update_buttons, GameState,
+};
+
+#[derive(Component)]
+struct DifficultyMenu;
+
+#[derive(Component)]
+struct BackButton;
+
+#[derive(Component)]
+struct PresetButton(DifficultyPreset);
+
+// the track of a stat's slider, clicking or dragging anywhere along it sets the stat
+#[derive(Component)]
+struct Slider(DifficultyStat);
+
+// the filled part of a slider, up to the stat's current value
+#[derive(Component)]
+struct SliderFill(DifficultyStat);
+
+#[derive(Component)]
+struct SliderLabel(DifficultyStat);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DifficultyStat {
+    ScaleGrowth,
+    SpawnSpeedup,
+    EnemyDamage,
+    EnemyHealth,
+    PlayerHealth,
+    RespecRefund,
+    Respecs,
+}
+
+impl DifficultyStat {
+    const ALL: [DifficultyStat; 7] = [
+        DifficultyStat::ScaleGrowth,
+        DifficultyStat::SpawnSpeedup,
+        DifficultyStat::EnemyDamage,
+        DifficultyStat::EnemyHealth,
+        DifficultyStat::PlayerHealth,
+        DifficultyStat::RespecRefund,
+        DifficultyStat::Respecs,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            DifficultyStat::ScaleGrowth => "Enemy growth",
+            DifficultyStat::SpawnSpeedup => "Spawn speedup",
+            DifficultyStat::EnemyDamage => "Enemy damage",
+            DifficultyStat::EnemyHealth => "Enemy health",
+            DifficultyStat::PlayerHealth => "Player health",
+            DifficultyStat::RespecRefund => "Respec refund",
+            DifficultyStat::Respecs => "Respecs",
+        }
+    }
+
+    // step size, min and max
+    fn range(&self) -> (f32, f32, f32) {
+        match self {
+            DifficultyStat::ScaleGrowth => (0.001, 1.0, 1.02),
+            DifficultyStat::SpawnSpeedup => (0.005, 1.0, 1.05),
+            DifficultyStat::EnemyDamage => (2.0, 2.0, 50.0),
+            DifficultyStat::EnemyHealth => (20.0, 20.0, 500.0),
+            DifficultyStat::PlayerHealth => (25.0, 50.0, 1000.0),
+            DifficultyStat::RespecRefund => (0.05, 0.0, 1.0),
+            DifficultyStat::Respecs => (1.0, 0.0, 5.0),
+        }
+    }
+
+    fn value(&self, difficulty: &Difficulty) -> f32 {
+        match self {
+            DifficultyStat::ScaleGrowth => difficulty.scale_growth,
+            DifficultyStat::SpawnSpeedup => difficulty.spawn_speedup,
+            DifficultyStat::EnemyDamage => difficulty.enemy_damage,
+            DifficultyStat::EnemyHealth => difficulty.enemy_health,
+            DifficultyStat::PlayerHealth => difficulty.player_health,
+            DifficultyStat::RespecRefund => difficulty.respec_refund,
+            DifficultyStat::Respecs => difficulty.respecs as f32,
+        }
+    }
+
+    // how far along its slider the stat is, from 0 to 1
+    fn fraction(&self, difficulty: &Difficulty) -> f32 {
+        let (_, min, max) = self.range();
+        (self.value(difficulty) - min) / (max - min)
+    }
+
+    // the value at a point along the slider, snapped to the step size
+    fn value_at(&self, fraction: f32) -> f32 {
+        let (step, min, max) = self.range();
+        let steps = ((max - min) * fraction.clamp(0.0, 1.0) / step).round();
+        (min + steps * step).min(max)
+    }
+
+    fn set_value(&self, difficulty: &mut Difficulty, value: f32) {
+        match self {
+            DifficultyStat::ScaleGrowth => difficulty.scale_growth = value,
+            DifficultyStat::SpawnSpeedup => difficulty.spawn_speedup = value,
+            DifficultyStat::EnemyDamage => difficulty.enemy_damage = value,
+            DifficultyStat::EnemyHealth => difficulty.enemy_health = value,
+            DifficultyStat::PlayerHealth => difficulty.player_health = value,
+            DifficultyStat::RespecRefund => difficulty.respec_refund = value,
+            DifficultyStat::Respecs => difficulty.respecs = value.round() as u32,
+        }
+    }
+
+    fn label(&self, difficulty: &Difficulty) -> String {
+        match self {
+            // these are per second multipliers, show them as percentages
+            DifficultyStat::ScaleGrowth => {
+                format!("+{:.1}%/s", (difficulty.scale_growth - 1.0) * 100.0)
+            }
+            DifficultyStat::SpawnSpeedup => {
+                format!("+{:.1}%/s", (difficulty.spawn_speedup - 1.0) * 100.0)
+            }
+            DifficultyStat::EnemyDamage => format!("{:.0}", difficulty.enemy_damage),
+            DifficultyStat::EnemyHealth => format!("{:.0}", difficulty.enemy_health),
+            DifficultyStat::PlayerHealth => format!("{:.0}", difficulty.player_health),
+            DifficultyStat::RespecRefund => format!("{:.0}%", difficulty.respec_refund * 100.0),
+            DifficultyStat::Respecs => format!("{}", difficulty.respecs),
+        }
+    }
+}
+
+pub struct DifficultyPlugin;
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Difficulty>()
+            .add_startup_system(load_difficulty)
+            // daily runs swap in a difficulty of their own
+            .add_enter_system(GameState::Menu, load_difficulty)
+            .add_enter_system(GameState::Difficulty, spawn_difficulty_menu)
+            .add_exit_system(GameState::Difficulty, despawn_with::<DifficultyMenu>)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Difficulty)
+                    .with_system(update_buttons)
+                    .with_system(pick_preset)
+                    .with_system(drag_slider)
+                    .with_system(update_sliders)
+                    .into(),
+            )
+            .add_system(
+                to_menu
+                    .run_in_state(GameState::Difficulty)
+                    .run_if(button_pressed::<BackButton>),
+            );
+    }
+}
+
+pub fn load_difficulty(mut difficulty: ResMut<Difficulty>, pkv: Res<PkvStore>) {
+    if let Ok(saved) = pkv.get::<Difficulty>("difficulty") {
+        *difficulty = saved;
+    }
+}
+
+fn spawn_difficulty_menu(mut commands: Commands, fonts: Res<Fonts>, difficulty: Res<Difficulty>) {
+    let text_style = TextStyle {
+        font: fonts.main.clone(),
+        font_size: 35.0,
+        color: Color::WHITE,
+    };
+
+    let small_button = Style {
+        size: Size::new(Val::Px(50.0), Val::Px(50.0)),
+        margin: UiRect::all(Val::Px(6.0)),
+        align_items: AlignItems::Center,
+        justify_content: JustifyContent::Center,
+        ..default()
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            color: UiColor([0.0; 4].into()),
+            style: Style {
+                margin: UiRect::all(Val::Auto),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(DifficultyMenu)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                format!("Difficulty: {}", difficulty.preset.name()),
+                TextStyle {
+                    color: Color::YELLOW,
+                    font_size: 50.0,
+                    ..text_style.clone()
+                },
+            ));
+
+            // presets
+            parent
+                .spawn_bundle(NodeBundle {
+                    color: UiColor([0.0; 4].into()),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for preset in DifficultyPreset::ALL {
+                        // custom is picked by changing a stat below
+                        if preset == DifficultyPreset::Custom {
+                            continue;
+                        }
+
+                        parent
+                            .spawn_bundle(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(220.0), Val::Px(65.0)),
+                                    ..small_button.clone()
+                                },
+                                ..default()
+                            })
+                            .insert(PresetButton(preset))
+                            .with_children(|parent| {
+                                parent.spawn_bundle(TextBundle::from_section(
+                                    preset.name(),
+                                    TextStyle {
+                                        color: if preset == difficulty.preset {
+                                            Color::YELLOW
+                                        } else {
+                                            Color::WHITE
+                                        },
+                                        ..text_style.clone()
+                                    },
+                                ));
+                            });
+                    }
+                });
+
+            // custom stats
+            for stat in DifficultyStat::ALL {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        color: UiColor([0.0; 4].into()),
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(300.0), Val::Auto),
+                                ..default()
+                            },
+                            text: Text::from_section(stat.name(), text_style.clone()),
+                            ..default()
+                        });
+
+                        parent
+                            .spawn_bundle(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(300.0), Val::Px(40.0)),
+                                    padding: UiRect::all(Val::Px(10.0)),
+                                    ..small_button.clone()
+                                },
+                                ..default()
+                            })
+                            .insert(Slider(stat))
+                            .with_children(|parent| {
+                                // the groove, the fill grows along it
+                                parent
+                                    .spawn_bundle(NodeBundle {
+                                        color: UiColor(Color::DARK_GRAY),
+                                        style: Style {
+                                            size: Size::new(
+                                                Val::Percent(100.0),
+                                                Val::Percent(100.0),
+                                            ),
+                                            ..default()
+                                        },
+                                        ..default()
+                                    })
+                                    .with_children(|parent| {
+                                        parent
+                                            .spawn_bundle(NodeBundle {
+                                                color: UiColor(Color::YELLOW),
+                                                style: Style {
+                                                    size: Size::new(
+                                                        Val::Percent(
+                                                            stat.fraction(&difficulty) * 100.0,
+                                                        ),
+                                                        Val::Percent(100.0),
+                                                    ),
+                                                    ..default()
+                                                },
+                                                ..default()
+                                            })
+                                            .insert(SliderFill(stat));
+                                    });
+                            });
+
+                        parent
+                            .spawn_bundle(TextBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(150.0), Val::Auto),
+                                    justify_content: JustifyContent::Center,
+                                    ..default()
+                                },
+                                text: Text::from_section(
+                                    stat.label(&difficulty),
+                                    TextStyle {
+                                        color: Color::YELLOW,
+                                        ..text_style.clone()
+                                    },
+                                )
+                                .with_alignment(TextAlignment::CENTER),
+                                ..default()
+                            })
+                            .insert(SliderLabel(stat));
+                    });
+            }
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+                        ..small_button.clone()
+                    },
+                    ..default()
+                })
+                .insert(BackButton)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section("Back", text_style.clone()));
+                });
+        });
+}
+
+fn save_difficulty(commands: &mut Commands, difficulty: &Difficulty, pkv: &mut PkvStore) {
+    pkv.set("difficulty", difficulty)
+        .expect("failed to access pkv store");
+
+    // re-entering the state rebuilds the labels
+    commands.insert_resource(NextState(GameState::Difficulty));
+}
+
+fn pick_preset(
+    mut commands: Commands,
+    mut difficulty: ResMut<Difficulty>,
+    mut pkv: ResMut<PkvStore>,
+    buttons: Query<(&Interaction, &PresetButton), Changed<Interaction>>,
+) {
+    for (interaction, preset) in &buttons {
+        if *interaction == Interaction::Clicked {
+            *difficulty = Difficulty::preset(preset.0);
+            save_difficulty(&mut commands, &difficulty, &mut pkv);
+        }
+    }
+}
+
+// a drag starts on a slider's track and follows the cursor until the mouse button is let go,
+// the difficulty is only saved once it's over since saving rebuilds the whole menu
+fn drag_slider(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    mouse: Res<Input<MouseButton>>,
+    mut difficulty: ResMut<Difficulty>,
+    mut pkv: ResMut<PkvStore>,
+    mut dragging: Local<Option<DifficultyStat>>,
+    sliders: Query<(&Interaction, &Slider, &Node, &GlobalTransform)>,
+) {
+    if dragging.is_none() {
+        *dragging = sliders
+            .iter()
+            .find(|(interaction, ..)| **interaction == Interaction::Clicked)
+            .map(|(_, slider, ..)| slider.0);
+    }
+
+    let stat = if let Some(stat) = *dragging {
+        stat
+    } else {
+        return;
+    };
+
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position());
+    let track = sliders.iter().find(|(_, slider, ..)| slider.0 == stat);
+    if let (Some(cursor), Some((_, _, node, transform))) = (cursor, track) {
+        // ui nodes are positioned by their center in window coordinates, just like the cursor
+        let left = transform.translation().x - node.size.x / 2.0;
+        let value = stat.value_at((cursor.x - left) / node.size.x);
+
+        if value != stat.value(&difficulty) {
+            stat.set_value(&mut difficulty, value);
+
+            // any tweak makes it a custom difficulty with its own high score
+            difficulty.preset = DifficultyPreset::Custom;
+        }
+    }
+
+    if !mouse.pressed(MouseButton::Left) {
+        *dragging = None;
+        save_difficulty(&mut commands, &difficulty, &mut pkv);
+    }
+}
+
+fn update_sliders(
+    difficulty: Res<Difficulty>,
+    mut fills: Query<(&SliderFill, &mut Style)>,
+    mut labels: Query<(&SliderLabel, &mut Text)>,
+) {
+    if !difficulty.is_changed() {
+        return;
+    }
+
+    for (fill, mut style) in &mut fills {
+        style.size.width = Val::Percent(fill.0.fraction(&difficulty) * 100.0);
+    }
+
+    for (label, mut text) in &mut labels {
+        text.sections[0].value = label.0.label(&difficulty);
+    }
+}
+
+fn to_menu(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Menu));
+}
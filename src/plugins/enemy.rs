@@ -2,13 +2,71 @@ use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 use iyes_loopless::prelude::*;
 use rand::{distributions::Standard, prelude::*};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    components::{AttackTimer, Damage, Enemy, Health, Player},
-    resources::{EnemyScale, ScaleTimer, Sounds, Sprites, SpawnTimer},
-    GameState,
+    components::{AttackTimer, Damage, Downed, Enemy, Health, Player, Stunned},
+    nearest_tank,
+    resources::{Difficulty, EnemyScale, ScaleTimer, Sounds, SpawnTimer, Sprites, ARENA_SIZE},
+    Buffs, DamageEvent, DamageKind, GameState, PowerUp, RangedAttack, RunModifiers, RunRng,
+    ShakeEvent, ZOMBIE_GROUP,
 };
 
+// how many of the spawned enemies are spitters
+const SPITTER_CHANCE: f32 = 0.15;
+
+#[derive(Component, Clone, Copy, Serialize, Deserialize)]
+pub enum EnemyKind {
+    Zombie,
+    // keeps its distance and spits at tanks from there
+    Spitter,
+}
+
+impl EnemyKind {
+    fn texture(&self, sprites: &Sprites) -> Handle<Image> {
+        match self {
+            EnemyKind::Zombie => sprites.enemy.clone(),
+            EnemyKind::Spitter => sprites.spitter.clone(),
+        }
+    }
+
+    // of the health set by the difficulty
+    fn health(&self) -> f32 {
+        match self {
+            EnemyKind::Zombie => 1.0,
+            EnemyKind::Spitter => 0.6,
+        }
+    }
+
+    // how close it wants to get to a tank
+    fn reach(&self) -> f32 {
+        match self {
+            EnemyKind::Zombie => 0.0,
+            EnemyKind::Spitter => 350.0,
+        }
+    }
+
+    // the chance of each power-up dropping when one of these dies, a kill drops at most one
+    pub fn drops(&self) -> &'static [(PowerUp, f32)] {
+        match self {
+            EnemyKind::Zombie => &[
+                (PowerUp::Heal, 0.03),
+                (PowerUp::RapidFire, 0.02),
+                (PowerUp::TripleShot, 0.02),
+                (PowerUp::Invulnerable, 0.01),
+                (PowerUp::Nuke, 0.005),
+            ],
+            EnemyKind::Spitter => &[
+                (PowerUp::Heal, 0.06),
+                (PowerUp::RapidFire, 0.04),
+                (PowerUp::TripleShot, 0.04),
+                (PowerUp::Invulnerable, 0.02),
+                (PowerUp::Nuke, 0.01),
+            ],
+        }
+    }
+}
+
 pub struct EnemyPlugin;
 pub enum EnemySpawnPos {
     Up,
@@ -48,15 +106,16 @@ impl Distribution<EnemySpawnPos> for Standard {
 }
 fn scale_enemies(
     time: Res<Time>,
+    difficulty: Res<Difficulty>,
     mut enemy_scale: ResMut<EnemyScale>,
     mut scale_timer: ResMut<ScaleTimer>,
     mut spawn_timer: ResMut<SpawnTimer>,
 ) {
     scale_timer.tick(time.delta());
     if scale_timer.just_finished() {
-        enemy_scale.0 *= 1.005;
+        enemy_scale.0 *= difficulty.scale_growth;
         let duration = spawn_timer.duration();
-        spawn_timer.set_duration(duration.div_f32(1.01));
+        spawn_timer.set_duration(duration.div_f32(difficulty.spawn_speedup));
     }
 }
 
@@ -64,93 +123,169 @@ fn spawn_enemies(
     mut commands: Commands,
     time: Res<Time>,
     enemy_scale: Res<EnemyScale>,
+    difficulty: Res<Difficulty>,
+    modifiers: Res<RunModifiers>,
     mut spawn_timer: ResMut<SpawnTimer>,
-    windows: Res<Windows>,
+    mut rng: ResMut<RunRng>,
     sprites: Res<Sprites>,
 ) {
     spawn_timer.tick(time.delta());
 
     if spawn_timer.just_finished() {
-        let window = windows.primary();
+        let half_size = ARENA_SIZE / 2.0;
 
-        let mut rng = rand::thread_rng();
         let enemy_pos: EnemySpawnPos = rng.gen();
 
         let translation = match enemy_pos {
-            EnemySpawnPos::Up => Vec3::new(
-                rng.gen_range((-window.width() / 2.0)..(window.width() / 2.0)),
-                -window.height() / 2.,
-                1.,
-            ),
-            EnemySpawnPos::Down => Vec3::new(
-                rng.gen_range((-window.width() / 2.0)..(window.width() / 2.0)),
-                window.height() / 2.,
-                1.,
-            ),
-            EnemySpawnPos::Left => Vec3::new(
-                -window.width() / 2.,
-                rng.gen_range((-window.height() / 2.0)..(window.height() / 2.0)),
-                1.,
-            ),
-            EnemySpawnPos::Right => Vec3::new(
-                window.width() / 2.,
-                rng.gen_range((-window.height() / 2.0)..(window.height() / 2.0)),
-                1.,
-            ),
+            EnemySpawnPos::Up => {
+                Vec3::new(rng.gen_range(-half_size.x..half_size.x), -half_size.y, 1.)
+            }
+            EnemySpawnPos::Down => {
+                Vec3::new(rng.gen_range(-half_size.x..half_size.x), half_size.y, 1.)
+            }
+            EnemySpawnPos::Left => {
+                Vec3::new(-half_size.x, rng.gen_range(-half_size.y..half_size.y), 1.)
+            }
+            EnemySpawnPos::Right => {
+                Vec3::new(half_size.x, rng.gen_range(-half_size.y..half_size.y), 1.)
+            }
         };
 
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: sprites.enemy.clone(),
-                transform: Transform::from_translation(translation).with_scale(Vec3::splat(6.0)),
-                ..default()
-            })
-            .insert(Enemy)
-            .insert(AttackTimer(Timer::from_seconds(0.5, true)))
-            .insert(Damage(10.0 * enemy_scale.0))
-            .insert(Health::new(100.0 * enemy_scale.0))
-            .insert(RigidBody::Dynamic)
-            .insert(ExternalImpulse::default())
-            .insert(ExternalForce::default())
-            .insert(ColliderMassProperties::Density(0.0))
-            .insert(AdditionalMassProperties::Mass(10.0))
-            .insert(Velocity::default())
-            .insert(Collider::cuboid(5.0, 7.0))
-            .insert(LockedAxes::ROTATION_LOCKED)
-            .insert(ActiveEvents::COLLISION_EVENTS);
+        let kind = if rng.gen::<f32>() < SPITTER_CHANCE {
+            EnemyKind::Spitter
+        } else {
+            EnemyKind::Zombie
+        };
+
+        spawn_enemy(
+            &mut commands,
+            &sprites,
+            &difficulty,
+            &modifiers,
+            kind,
+            translation,
+            enemy_scale.0,
+        );
+    }
+}
+
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    sprites: &Sprites,
+    difficulty: &Difficulty,
+    modifiers: &RunModifiers,
+    kind: EnemyKind,
+    translation: Vec3,
+    scale: f32,
+) -> Entity {
+    let effects = modifiers.effects();
+
+    let mut enemy = commands.spawn_bundle(SpriteBundle {
+        texture: kind.texture(sprites),
+        transform: Transform::from_translation(translation)
+            .with_scale(Vec3::splat(6.0 * effects.enemy_size)),
+        ..default()
+    });
+
+    enemy
+        .insert(Enemy)
+        .insert(kind)
+        .insert(AttackTimer(Timer::from_seconds(0.5, true)))
+        .insert(Damage(difficulty.enemy_damage * scale))
+        .insert(Health::new(
+            difficulty.enemy_health * effects.enemy_health * kind.health() * scale,
+        ))
+        .insert(RigidBody::Dynamic)
+        .insert(ExternalImpulse::default())
+        .insert(ExternalForce::default())
+        .insert(ColliderMassProperties::Density(0.0))
+        .insert(AdditionalMassProperties::Mass(10.0))
+        .insert(Velocity::default())
+        .insert(Collider::cuboid(5.0, 7.0))
+        .insert(LockedAxes::ROTATION_LOCKED)
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(CollisionGroups::new(ZOMBIE_GROUP, u32::MAX));
+
+    if let EnemyKind::Spitter = kind {
+        enemy.insert(RangedAttack::default());
     }
+
+    enemy.id()
 }
 
 fn move_to_player(
-    mut enemies: Query<(&mut Transform, &mut ExternalForce), With<Enemy>>,
-    player: Query<&Transform, (With<Player>, Without<Enemy>)>,
+    mut enemies: Query<
+        (
+            &mut Transform,
+            &mut ExternalForce,
+            &EnemyKind,
+            Option<&RangedAttack>,
+            Option<&Stunned>,
+        ),
+        With<Enemy>,
+    >,
+    tanks: Query<&Transform, (With<Player>, Without<Downed>, Without<Enemy>)>,
+    modifiers: Res<RunModifiers>,
 ) {
-    let player_transform = player.single();
+    for (mut transform, mut velocity, kind, attack, stunned) in &mut enemies {
+        if stunned.is_some() {
+            velocity.force = Vec2::ZERO;
+            continue;
+        }
 
-    for (mut transform, mut velocity) in &mut enemies {
-        let dir = (player_transform.translation.truncate() - transform.translation.truncate())
-            .normalize();
+        let position = transform.translation.truncate();
+        let target = if let Some(target) = nearest_tank(&tanks, position) {
+            target
+        } else {
+            return;
+        };
+
+        let dir = (target - position).normalize();
 
         transform.rotation = Quat::from_rotation_z(Vec2::X.angle_between(dir));
 
-        velocity.force = dir * 1000.0;
+        // spitters hold still while they're in reach or winding up to spit
+        if target.distance(position) <= kind.reach()
+            || attack.map_or(false, RangedAttack::winding_up)
+        {
+            velocity.force = Vec2::ZERO;
+            continue;
+        }
+
+        velocity.force = dir * 1000.0 * modifiers.effects().enemy_speed;
     }
 }
 
 fn damage_player(
     time: Res<Time>,
-    mut enemies: Query<(&Transform, &mut AttackTimer, &Damage), With<Enemy>>,
-    mut player: Query<(&Transform, &mut Health), With<Player>>,
+    mut enemies: Query<(&Transform, &mut AttackTimer, &Damage), (With<Enemy>, Without<Stunned>)>,
+    mut tanks: Query<(&Transform, &mut Health, &Buffs), (With<Player>, Without<Downed>)>,
     audio: Res<Audio>,
     sound: Res<Sounds>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut shake_events: EventWriter<ShakeEvent>,
 ) {
-    let (player_transform, mut health) = player.single_mut();
-
     for (enemy_transform, mut attack_timer, damage) in &mut enemies {
-        let dist = player_transform
-            .translation
-            .truncate()
-            .distance(enemy_transform.translation.truncate());
+        let position = enemy_transform.translation.truncate();
+
+        // zombies go for whichever tank they're closest to
+        let (player_transform, mut health, buffs) = if let Some(tank) =
+            tanks.iter_mut().min_by(|(a, ..), (b, ..)| {
+                let a = a.translation.truncate().distance(position);
+                let b = b.translation.truncate().distance(position);
+                a.total_cmp(&b)
+            }) {
+            tank
+        } else {
+            return;
+        };
+
+        // an invulnerable tank still draws them in, their bites just don't land
+        if buffs.has(PowerUp::Invulnerable) {
+            continue;
+        }
+
+        let dist = player_transform.translation.truncate().distance(position);
 
         const THRESHOLD: f32 = 100.0;
         if dist <= THRESHOLD {
@@ -161,6 +296,12 @@ fn damage_player(
                     PlaybackSettings::ONCE.with_volume(0.1),
                 );
                 health.0 -= damage.0;
+                damage_events.send(DamageEvent {
+                    position: player_transform.translation.truncate(),
+                    amount: damage.0,
+                    kind: DamageKind::Player,
+                });
+                shake_events.send(ShakeEvent(0.4));
             }
         }
     }
@@ -4,10 +4,12 @@ use iyes_loopless::prelude::*;
 
 use crate::{
     button_pressed,
-    components::{Bullet, Damage, Enemy, Knockback, Pierce, Player, Coin},
-    despawn_with,
-    resources::{BulletType, Coins, Fonts, ShootTimer, Spread, SpawnTimer, EnemyScale},
-    update_buttons, GameState,
+    components::{Bullet, Coin, Enemy, Player},
+    despawn_with, record_daily_score,
+    resources::{
+        Coins, Difficulty, EnemyScale, Fonts, MetaProgress, Score, SpawnTimer, SurvivalTime,
+    },
+    update_buttons, GameState, RunModifiers, RunStats,
 };
 
 #[derive(Component)]
@@ -41,23 +43,42 @@ impl Plugin for GameOverPlugin {
 fn show_game_over(
     mut commands: Commands,
     fonts: Res<Fonts>,
-    coins: Res<Coins>,
+    score: Res<Score>,
+    survival_time: Res<SurvivalTime>,
+    stats: Res<RunStats>,
+    difficulty: Res<Difficulty>,
+    modifiers: Res<RunModifiers>,
     mut pkv: ResMut<PkvStore>,
 ) {
-    let high_score = if let Ok(high_score) = pkv.get::<u32>("high_score") {
-        if coins.0 > high_score {
-            pkv.set::<u32>("high_score", &coins.0)
-                .expect("failed to access pkv store");
+    // daily runs only count towards the daily best
+    let (high_score_label, high_score) = if modifiers.daily {
+        let day = modifiers.seed.unwrap_or_default();
+        (
+            "Daily Best".to_string(),
+            record_daily_score(&mut pkv, day, score.0),
+        )
+    } else {
+        let key = difficulty.high_score_key();
+        let high_score = if let Ok(high_score) = pkv.get::<u32>(&key) {
+            if score.0 > high_score {
+                pkv.set::<u32>(&key, &score.0)
+                    .expect("failed to access pkv store");
 
-            coins.0
+                score.0
+            } else {
+                high_score
+            }
         } else {
-            high_score
-        }
-    } else {
-        pkv.set::<u32>("high_score", &coins.0)
-            .expect("failed to access pkv store");
+            pkv.set::<u32>(&key, &score.0)
+                .expect("failed to access pkv store");
 
-        coins.0
+            score.0
+        };
+
+        (
+            format!("High Score ({})", difficulty.preset.name()),
+            high_score,
+        )
     };
 
     commands
@@ -84,7 +105,7 @@ fn show_game_over(
             ));
 
             parent.spawn_bundle(TextBundle::from_section(
-                format!("Score: {}", coins.0),
+                format!("Score: {}", score.0),
                 TextStyle {
                     color: Color::YELLOW,
                     font: fonts.main.clone(),
@@ -93,7 +114,7 @@ fn show_game_over(
             ));
 
             parent.spawn_bundle(TextBundle::from_section(
-                format!("High Score: {}", high_score),
+                format!("{}: {}", high_score_label, high_score),
                 TextStyle {
                     color: Color::YELLOW,
                     font: fonts.main.clone(),
@@ -101,6 +122,29 @@ fn show_game_over(
                 },
             ));
 
+            parent.spawn_bundle(TextBundle::from_section(
+                format!(
+                    "Scrap: +{}",
+                    MetaProgress::reward(score.0, survival_time.elapsed_secs())
+                ),
+                TextStyle {
+                    color: Color::GRAY,
+                    font: fonts.main.clone(),
+                    font_size: 35.0,
+                },
+            ));
+
+            for line in stats.lines() {
+                parent.spawn_bundle(TextBundle::from_section(
+                    line,
+                    TextStyle {
+                        color: Color::WHITE,
+                        font: fonts.main.clone(),
+                        font_size: 22.0,
+                    },
+                ));
+            }
+
             parent
                 .spawn_bundle(ButtonBundle {
                     color: UiColor(Color::BLACK),
@@ -131,22 +175,14 @@ fn to_menu(mut commands: Commands) {
 
 fn reset_stats(
     mut coins: ResMut<Coins>,
-    mut damage: ResMut<Damage>,
-    mut knockback: ResMut<Knockback>,
-    mut spread: ResMut<Spread>,
-    mut pierce: ResMut<Pierce>,
-    mut shoot_timer: ResMut<ShootTimer>,
-    mut bullet_type: ResMut<BulletType>,
+    mut score: ResMut<Score>,
+    mut survival_time: ResMut<SurvivalTime>,
     mut spawn_timer: ResMut<SpawnTimer>,
     mut enemy_scale: ResMut<EnemyScale>,
 ) {
     coins.0 = 0;
-    *damage = default();
-    *knockback = default();
-    *spread = default();
-    *pierce = default();
-    *shoot_timer = default();
-    *bullet_type = default();
+    score.0 = 0;
+    *survival_time = default();
 
     *spawn_timer = default();
     *enemy_scale = default();
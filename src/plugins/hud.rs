@@ -0,0 +1,696 @@
+use std::f32::consts::TAU;
+
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use iyes_loopless::prelude::*;
+
+use crate::{
+    components::{BulletType, Controls, Health, Heat, Loadout, Player},
+    despawn_with,
+    resources::{Coins, Fonts, Score, Settings, Sprites, SurvivalTime},
+    run_starting, slot_label, AbilitySlots, Buffs, GameState, Modifier, PowerUp, RunModifiers,
+    Upgrades,
+};
+
+#[derive(Component)]
+struct Hud;
+
+// these belong to the tank with the same index
+#[derive(Component)]
+struct HudHealthBar(usize);
+
+// only there in runs with the overheat mutator
+#[derive(Component)]
+struct HudHeat(usize);
+
+#[derive(Component)]
+struct HudCoins;
+
+#[derive(Component)]
+struct HudScore;
+
+#[derive(Component)]
+struct HudTimer;
+
+#[derive(Component)]
+struct HudWeapon(usize);
+
+#[derive(Component)]
+struct HudUpgrades(usize);
+
+#[derive(Component)]
+struct HudAbilities(usize);
+
+// remembers which power-ups it shows, so it's only rebuilt when they change
+#[derive(Component)]
+struct HudBuffs {
+    player: usize,
+    shown: Vec<PowerUp>,
+}
+
+// the seconds left on one of the power-ups in a `HudBuffs` row
+#[derive(Component)]
+struct HudBuffTime {
+    player: usize,
+    index: usize,
+}
+
+// darkens an ability icon for as long as it's cooling down
+#[derive(Component)]
+struct HudCooldown {
+    player: usize,
+    slot: usize,
+}
+
+// frames of a shade that sweeps around clockwise, the first one is empty and the last one full
+struct CooldownSweep(Vec<Handle<Image>>);
+
+const SWEEP_FRAMES: usize = 32;
+const SWEEP_SIZE: u32 = 32;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(build_cooldown_sweep)
+            .add_enter_system(GameState::Playing, spawn_hud.run_if(run_starting))
+            .add_enter_system(GameState::GameOver, despawn_with::<Hud>)
+            .add_enter_system(GameState::Menu, despawn_with::<Hud>)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_not_in_state(GameState::Loading)
+                    .run_not_in_state(GameState::Menu)
+                    .with_system(update_health)
+                    .with_system(update_coins)
+                    .with_system(update_score)
+                    .with_system(update_timer)
+                    .with_system(update_weapon)
+                    .with_system(update_upgrades)
+                    .with_system(update_abilities)
+                    .with_system(update_cooldowns)
+                    .with_system(update_buffs)
+                    .with_system(update_buff_times)
+                    .with_system(update_heat)
+                    .into(),
+            );
+    }
+}
+
+// bevy's ui can't draw a radial sweep, so every step of one is drawn up front
+fn build_cooldown_sweep(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let center = SWEEP_SIZE as f32 / 2.0;
+
+    let frames = (0..=SWEEP_FRAMES)
+        .map(|frame| {
+            let left = frame as f32 / SWEEP_FRAMES as f32;
+            let mut data = Vec::with_capacity((SWEEP_SIZE * SWEEP_SIZE * 4) as usize);
+
+            for y in 0..SWEEP_SIZE {
+                for x in 0..SWEEP_SIZE {
+                    // clockwise from straight up, the part that's still cooling down is shaded
+                    let dx = x as f32 + 0.5 - center;
+                    let dy = center - (y as f32 + 0.5);
+                    let turn = (dx.atan2(dy) / TAU).rem_euclid(1.0);
+                    let alpha = if turn >= 1.0 - left { 178 } else { 0 };
+
+                    data.extend_from_slice(&[0, 0, 0, alpha]);
+                }
+            }
+
+            images.add(Image::new(
+                Extent3d {
+                    width: SWEEP_SIZE,
+                    height: SWEEP_SIZE,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                data,
+                TextureFormat::Rgba8UnormSrgb,
+            ))
+        })
+        .collect();
+
+    commands.insert_resource(CooldownSweep(frames));
+}
+
+fn corner(position: UiRect<Val>, align_items: AlignItems) -> NodeBundle {
+    NodeBundle {
+        color: UiColor([0.0; 4].into()),
+        style: Style {
+            position_type: PositionType::Absolute,
+            position,
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items,
+            padding: UiRect::all(Val::Px(15.0)),
+            ..default()
+        },
+        ..default()
+    }
+}
+
+fn spawn_hud(
+    mut commands: Commands,
+    fonts: Res<Fonts>,
+    sprites: Res<Sprites>,
+    settings: Res<Settings>,
+    modifiers: Res<RunModifiers>,
+) {
+    let text_style = TextStyle {
+        color: Color::YELLOW,
+        font: fonts.main.clone(),
+        font_size: 30.0,
+    };
+
+    let tanks = if settings.co_op { 2 } else { 1 };
+
+    // top left: health of every tank and the shared coins
+    commands
+        .spawn_bundle(corner(
+            UiRect {
+                top: Val::Px(0.0),
+                left: Val::Px(0.0),
+                ..default()
+            },
+            AlignItems::FlexStart,
+        ))
+        .insert(Hud)
+        .with_children(|parent| {
+            for index in 0..tanks {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        color: UiColor(Color::RED),
+                        style: Style {
+                            size: Size::new(Val::Px(250.0), Val::Px(20.0)),
+                            margin: UiRect {
+                                bottom: Val::Px(6.0),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                color: UiColor(Color::GREEN),
+                                style: Style {
+                                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .insert(HudHealthBar(index));
+                    });
+
+                if modifiers.has(Modifier::Overheat) {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            color: UiColor(Color::DARK_GRAY),
+                            style: Style {
+                                size: Size::new(Val::Px(250.0), Val::Px(8.0)),
+                                margin: UiRect {
+                                    bottom: Val::Px(6.0),
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn_bundle(NodeBundle {
+                                    color: UiColor(Color::ORANGE),
+                                    style: Style {
+                                        size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                                        ..default()
+                                    },
+                                    ..default()
+                                })
+                                .insert(HudHeat(index));
+                        });
+                }
+            }
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    color: UiColor([0.0; 4].into()),
+                    style: Style {
+                        margin: UiRect {
+                            top: Val::Px(10.0),
+                            ..default()
+                        },
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn_bundle(ImageBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(20.0), Val::Px(20.0)),
+                            margin: UiRect {
+                                right: Val::Px(10.0),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        image: UiImage(sprites.coin.clone()),
+                        ..default()
+                    });
+                    parent
+                        .spawn_bundle(TextBundle::from_section("0", text_style.clone()))
+                        .insert(HudCoins);
+                });
+        });
+
+    // top right: score and survival time
+    commands
+        .spawn_bundle(corner(
+            UiRect {
+                top: Val::Px(0.0),
+                right: Val::Px(0.0),
+                ..default()
+            },
+            AlignItems::FlexEnd,
+        ))
+        .insert(Hud)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle::from_section("Score: 0", text_style.clone()))
+                .insert(HudScore);
+            parent
+                .spawn_bundle(TextBundle::from_section(
+                    "0:00",
+                    TextStyle {
+                        color: Color::WHITE,
+                        ..text_style.clone()
+                    },
+                ))
+                .insert(HudTimer);
+        });
+
+    // bottom corners: every tank's abilities, weapon and everything it bought so far, left for the first
+    for index in 0..tanks {
+        let (position, align_items) = if index == 0 {
+            (
+                UiRect {
+                    bottom: Val::Px(0.0),
+                    left: Val::Px(0.0),
+                    ..default()
+                },
+                AlignItems::FlexStart,
+            )
+        } else {
+            (
+                UiRect {
+                    bottom: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    ..default()
+                },
+                AlignItems::FlexEnd,
+            )
+        };
+
+        commands
+            .spawn_bundle(corner(position, align_items))
+            .insert(Hud)
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        color: UiColor([0.0; 4].into()),
+                        style: Style {
+                            size: Size::new(Val::Auto, Val::Px(32.0)),
+                            margin: UiRect {
+                                bottom: Val::Px(10.0),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .insert(HudBuffs {
+                        player: index,
+                        shown: Vec::new(),
+                    });
+
+                parent
+                    .spawn_bundle(NodeBundle {
+                        color: UiColor([0.0; 4].into()),
+                        style: Style {
+                            size: Size::new(Val::Auto, Val::Px(40.0)),
+                            margin: UiRect {
+                                bottom: Val::Px(10.0),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .insert(HudAbilities(index));
+
+                parent
+                    .spawn_bundle(NodeBundle {
+                        color: UiColor([0.0; 4].into()),
+                        style: Style {
+                            size: Size::new(Val::Auto, Val::Px(24.0)),
+                            margin: UiRect {
+                                bottom: Val::Px(10.0),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .insert(HudUpgrades(index));
+
+                parent
+                    .spawn_bundle(ImageBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(48.0), Val::Px(48.0)),
+                            ..default()
+                        },
+                        image: UiImage(sprites.bullet.clone()),
+                        ..default()
+                    })
+                    .insert(HudWeapon(index));
+            });
+    }
+}
+
+fn update_health(
+    tanks: Query<(&Player, &Health), Changed<Health>>,
+    mut bars: Query<(&HudHealthBar, &mut Style)>,
+) {
+    for (player, health) in &tanks {
+        for (bar, mut style) in &mut bars {
+            if bar.0 == player.0 {
+                style.size.width = Val::Percent((health.0 / health.1).max(0.0) * 100.0);
+            }
+        }
+    }
+}
+
+// turns red while the barrel is locked
+fn update_heat(
+    tanks: Query<(&Player, &Heat, &Loadout)>,
+    mut gauges: Query<(&HudHeat, &mut Style, &mut UiColor)>,
+) {
+    for (player, heat, loadout) in &tanks {
+        for (gauge, mut style, mut color) in &mut gauges {
+            if gauge.0 == player.0 {
+                style.size.width =
+                    Val::Percent((heat.value / loadout.heat_capacity).min(1.0) * 100.0);
+                color.0 = if heat.overheated {
+                    Color::RED
+                } else {
+                    Color::ORANGE
+                };
+            }
+        }
+    }
+}
+
+fn update_coins(coins: Res<Coins>, mut text: Query<&mut Text, With<HudCoins>>) {
+    if let Ok(mut text) = text.get_single_mut() {
+        if coins.is_changed() || text.is_added() {
+            text.sections[0].value = format!("{}", coins.0);
+        }
+    }
+}
+
+fn update_score(
+    score: Res<Score>,
+    modifiers: Res<RunModifiers>,
+    mut text: Query<&mut Text, With<HudScore>>,
+) {
+    if let Ok(mut text) = text.get_single_mut() {
+        if score.is_changed() || text.is_added() {
+            // the multiplier only counts once the run is over
+            let multiplier = modifiers.effects().score;
+            text.sections[0].value = if multiplier == 1.0 {
+                format!("Score: {}", score.0)
+            } else {
+                format!("Score: {} (x{:.2})", score.0, multiplier)
+            };
+        }
+    }
+}
+
+fn update_timer(time: Res<SurvivalTime>, mut text: Query<&mut Text, With<HudTimer>>) {
+    if let Ok(mut text) = text.get_single_mut() {
+        let seconds = time.elapsed_secs() as u32;
+        text.sections[0].value = format!("{}:{:02}", seconds / 60, seconds % 60);
+    }
+}
+
+fn update_weapon(
+    sprites: Res<Sprites>,
+    tanks: Query<(&Player, ChangeTrackers<Loadout>, &Loadout)>,
+    mut icons: Query<(&HudWeapon, &mut UiImage)>,
+) {
+    for (weapon, mut icon) in &mut icons {
+        for (player, tracker, loadout) in &tanks {
+            if player.0 == weapon.0 && (tracker.is_changed() || icon.is_added()) {
+                icon.0 = match loadout.bullet_type {
+                    BulletType::Regular => sprites.bullet.clone(),
+                    BulletType::Rocket => sprites.rocket.clone(),
+                    BulletType::SawBlade => sprites.saw_blade.clone(),
+                };
+            }
+        }
+    }
+}
+
+fn update_upgrades(
+    mut commands: Commands,
+    sprites: Res<Sprites>,
+    tanks: Query<(&Player, ChangeTrackers<Upgrades>, &Upgrades)>,
+    rows: Query<(Entity, &HudUpgrades, Option<&Children>)>,
+    added: Query<(), Added<HudUpgrades>>,
+) {
+    for (row, hud, children) in &rows {
+        let (tracker, upgrades) = if let Some((_, tracker, upgrades)) =
+            tanks.iter().find(|(player, ..)| player.0 == hud.0)
+        {
+            (tracker, upgrades)
+        } else {
+            continue;
+        };
+
+        if !tracker.is_changed() && !added.contains(row) {
+            continue;
+        }
+
+        if let Some(children) = children {
+            for child in children.iter() {
+                commands.entity(*child).despawn_recursive();
+            }
+        }
+
+        commands.entity(row).with_children(|parent| {
+            for upgrade in upgrades.0.iter() {
+                parent.spawn_bundle(ImageBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(24.0), Val::Px(24.0)),
+                        margin: UiRect {
+                            right: Val::Px(4.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    image: UiImage(upgrade.icon(&sprites)),
+                    ..default()
+                });
+            }
+        });
+    }
+}
+
+fn update_abilities(
+    mut commands: Commands,
+    fonts: Res<Fonts>,
+    sprites: Res<Sprites>,
+    sweep: Res<CooldownSweep>,
+    tanks: Query<(&Player, &Controls, &AbilitySlots)>,
+    rows: Query<(Entity, &HudAbilities, Option<&Children>)>,
+) {
+    for (row, hud, children) in &rows {
+        let (controls, slots) = if let Some((_, controls, slots)) =
+            tanks.iter().find(|(player, ..)| player.0 == hud.0)
+        {
+            (controls, slots)
+        } else {
+            continue;
+        };
+
+        // the cooldowns change every frame, but every purchase or respec changes the number of slots
+        let shown = children.map_or(0, |children| children.len());
+        if shown == slots.0.len() {
+            continue;
+        }
+
+        if let Some(children) = children {
+            for child in children.iter() {
+                commands.entity(*child).despawn_recursive();
+            }
+        }
+
+        commands.entity(row).with_children(|parent| {
+            for (index, slot) in slots.0.iter().enumerate() {
+                parent
+                    .spawn_bundle(ImageBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(40.0), Val::Px(40.0)),
+                            margin: UiRect {
+                                right: Val::Px(6.0),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        image: UiImage(slot.ability.upgrade().icon(&sprites)),
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(ImageBundle {
+                                style: Style {
+                                    position_type: PositionType::Absolute,
+                                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                                    ..default()
+                                },
+                                image: UiImage(sweep.0[0].clone()),
+                                ..default()
+                            })
+                            .insert(HudCooldown {
+                                player: hud.0,
+                                slot: index,
+                            });
+
+                        parent.spawn_bundle(TextBundle {
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                position: UiRect {
+                                    right: Val::Px(2.0),
+                                    bottom: Val::Px(0.0),
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            text: Text::from_section(
+                                slot_label(*controls, index),
+                                TextStyle {
+                                    color: Color::WHITE,
+                                    font: fonts.main.clone(),
+                                    font_size: 16.0,
+                                },
+                            ),
+                            ..default()
+                        });
+                    });
+            }
+        });
+    }
+}
+
+fn update_cooldowns(
+    sweep: Res<CooldownSweep>,
+    tanks: Query<(&Player, &AbilitySlots)>,
+    mut shades: Query<(&HudCooldown, &mut UiImage)>,
+) {
+    for (shade, mut image) in &mut shades {
+        let left = tanks
+            .iter()
+            .find(|(player, _)| player.0 == shade.player)
+            .and_then(|(_, slots)| slots.0.get(shade.slot))
+            .map_or(0.0, |slot| slot.cooldown.percent_left());
+
+        // rounded up so a cooldown that's barely started doesn't look ready
+        let frame = &sweep.0[(left * SWEEP_FRAMES as f32).ceil() as usize];
+        if image.0 != *frame {
+            image.0 = frame.clone();
+        }
+    }
+}
+
+fn update_buffs(
+    mut commands: Commands,
+    fonts: Res<Fonts>,
+    sprites: Res<Sprites>,
+    tanks: Query<(&Player, &Buffs)>,
+    mut rows: Query<(Entity, &mut HudBuffs, Option<&Children>)>,
+) {
+    for (row, mut hud, children) in &mut rows {
+        let active: Vec<PowerUp> = tanks
+            .iter()
+            .find(|(player, _)| player.0 == hud.player)
+            .map_or(Vec::new(), |(_, buffs)| {
+                buffs.0.iter().map(|(power_up, _)| *power_up).collect()
+            });
+
+        if active == hud.shown {
+            continue;
+        }
+
+        if let Some(children) = children {
+            for child in children.iter() {
+                commands.entity(*child).despawn_recursive();
+            }
+        }
+
+        commands.entity(row).with_children(|parent| {
+            for (index, power_up) in active.iter().enumerate() {
+                parent.spawn_bundle(ImageBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(32.0), Val::Px(32.0)),
+                        ..default()
+                    },
+                    image: UiImage(power_up.icon(&sprites)),
+                    ..default()
+                });
+
+                parent
+                    .spawn_bundle(TextBundle {
+                        style: Style {
+                            margin: UiRect {
+                                left: Val::Px(4.0),
+                                right: Val::Px(10.0),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        text: Text::from_section(
+                            "",
+                            TextStyle {
+                                color: Color::WHITE,
+                                font: fonts.main.clone(),
+                                font_size: 20.0,
+                            },
+                        ),
+                        ..default()
+                    })
+                    .insert(HudBuffTime {
+                        player: hud.player,
+                        index,
+                    });
+            }
+        });
+
+        hud.shown = active;
+    }
+}
+
+fn update_buff_times(tanks: Query<(&Player, &Buffs)>, mut texts: Query<(&HudBuffTime, &mut Text)>) {
+    for (time, mut text) in &mut texts {
+        let left = tanks
+            .iter()
+            .find(|(player, _)| player.0 == time.player)
+            .and_then(|(_, buffs)| buffs.0.get(time.index))
+            .map_or(0.0, |(_, timer)| {
+                timer.duration().as_secs_f32() - timer.elapsed_secs()
+            });
+
+        text.sections[0].value = format!("{:.0}s", left.ceil());
+    }
+}
@@ -9,3 +9,48 @@ pub use skill_tree::*;
 
 mod game_over;
 pub use game_over::*;
+
+mod damage_numbers;
+pub use damage_numbers::*;
+
+mod particles;
+pub use particles::*;
+
+mod camera;
+pub use camera::*;
+
+mod hud;
+pub use hud::*;
+
+mod workshop;
+pub use workshop::*;
+
+mod save;
+pub use save::*;
+
+mod achievements;
+pub use achievements::*;
+
+mod stats;
+pub use stats::*;
+
+mod difficulty;
+pub use difficulty::*;
+
+mod modifiers;
+pub use modifiers::*;
+
+mod daily;
+pub use daily::*;
+
+mod companions;
+pub use companions::*;
+
+mod abilities;
+pub use abilities::*;
+
+mod power_ups;
+pub use power_ups::*;
+
+mod projectiles;
+pub use projectiles::*;
@@ -0,0 +1,475 @@
+use bevy::prelude::*;
+use bevy_pkv::PkvStore;
+use iyes_loopless::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    button_pressed,
+    components::Loadout,
+    despawn_with,
+    resources::{Fonts, SpawnTimer},
+    run_starting, update_buttons, GameState, Upgrade,
+};
+
+// rules that change a whole run
+#[derive(Component, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Modifier {
+    SawbladesOnly,
+    FastZombies,
+    NoSkillTree,
+    Swarm,
+    GlassCannon,
+    CoinRain,
+    BigHeads,
+    Overheat,
+}
+
+// how a modifier changes the numbers of a run, the effects of several modifiers multiply
+#[derive(Clone, Copy)]
+pub struct ModifierEffects {
+    pub player_health: f32,
+    pub damage: f32,
+    pub enemy_health: f32,
+    pub enemy_speed: f32,
+    pub enemy_size: f32,
+    pub spawn_rate: f32,
+    pub coin_value: u32,
+    pub score: f32,
+}
+
+impl ModifierEffects {
+    const NONE: ModifierEffects = ModifierEffects {
+        player_health: 1.0,
+        damage: 1.0,
+        enemy_health: 1.0,
+        enemy_speed: 1.0,
+        enemy_size: 1.0,
+        spawn_rate: 1.0,
+        coin_value: 1,
+        score: 1.0,
+    };
+
+    fn stack(self, other: ModifierEffects) -> ModifierEffects {
+        ModifierEffects {
+            player_health: self.player_health * other.player_health,
+            damage: self.damage * other.damage,
+            enemy_health: self.enemy_health * other.enemy_health,
+            enemy_speed: self.enemy_speed * other.enemy_speed,
+            enemy_size: self.enemy_size * other.enemy_size,
+            spawn_rate: self.spawn_rate * other.spawn_rate,
+            coin_value: self.coin_value * other.coin_value,
+            score: self.score * other.score,
+        }
+    }
+}
+
+impl Modifier {
+    pub const ALL: [Modifier; 8] = [
+        Modifier::SawbladesOnly,
+        Modifier::FastZombies,
+        Modifier::NoSkillTree,
+        Modifier::Swarm,
+        Modifier::GlassCannon,
+        Modifier::CoinRain,
+        Modifier::BigHeads,
+        Modifier::Overheat,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Modifier::SawbladesOnly => "Sawblades Only",
+            Modifier::FastZombies => "Fast Zombies",
+            Modifier::NoSkillTree => "No Skill Tree",
+            Modifier::Swarm => "Swarm",
+            Modifier::GlassCannon => "Glass Cannon",
+            Modifier::CoinRain => "Coin Rain",
+            Modifier::BigHeads => "Big Heads",
+            Modifier::Overheat => "Overheat",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Modifier::SawbladesOnly => "Start with sawblades, no other bullets",
+            Modifier::FastZombies => "Zombies move twice as fast",
+            Modifier::NoSkillTree => "The skill tree can't be opened",
+            Modifier::Swarm => "Twice as many zombies with half the health",
+            Modifier::GlassCannon => "Double damage, half health",
+            Modifier::CoinRain => "Coins are worth double",
+            Modifier::BigHeads => "Zombies are bigger and easier to hit",
+            Modifier::Overheat => "Sustained fire overheats the barrel",
+        }
+    }
+
+    pub fn effects(&self) -> ModifierEffects {
+        match self {
+            Modifier::SawbladesOnly => ModifierEffects {
+                score: 1.1,
+                ..ModifierEffects::NONE
+            },
+            Modifier::FastZombies => ModifierEffects {
+                enemy_speed: 2.0,
+                score: 1.5,
+                ..ModifierEffects::NONE
+            },
+            Modifier::NoSkillTree => ModifierEffects {
+                score: 2.0,
+                ..ModifierEffects::NONE
+            },
+            Modifier::Swarm => ModifierEffects {
+                spawn_rate: 2.0,
+                enemy_health: 0.5,
+                score: 1.25,
+                ..ModifierEffects::NONE
+            },
+            Modifier::GlassCannon => ModifierEffects {
+                player_health: 0.5,
+                damage: 2.0,
+                score: 1.25,
+                ..ModifierEffects::NONE
+            },
+            Modifier::CoinRain => ModifierEffects {
+                coin_value: 2,
+                score: 0.5,
+                ..ModifierEffects::NONE
+            },
+            Modifier::BigHeads => ModifierEffects {
+                enemy_size: 1.5,
+                score: 0.75,
+                ..ModifierEffects::NONE
+            },
+            Modifier::Overheat => ModifierEffects {
+                score: 1.25,
+                ..ModifierEffects::NONE
+            },
+        }
+    }
+}
+
+// the modifiers of the current run, set before it starts and cleared back in the menu
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct RunModifiers {
+    pub modifiers: Vec<Modifier>,
+    // runs with a seed play out the same every time, otherwise one is picked at random
+    pub seed: Option<u64>,
+    pub daily: bool,
+}
+
+impl RunModifiers {
+    pub fn has(&self, modifier: Modifier) -> bool {
+        self.modifiers.contains(&modifier)
+    }
+
+    pub fn effects(&self) -> ModifierEffects {
+        self.modifiers
+            .iter()
+            .map(Modifier::effects)
+            .fold(ModifierEffects::NONE, ModifierEffects::stack)
+    }
+
+    // what the loadout looks like before any upgrades
+    pub fn base_loadout(&self) -> Loadout {
+        let mut loadout = Loadout::default();
+
+        if self.has(Modifier::SawbladesOnly) {
+            Upgrade::SawBlade.apply(&mut loadout);
+        }
+
+        loadout
+    }
+
+    pub fn allows(&self, upgrade: Upgrade) -> bool {
+        match upgrade {
+            Upgrade::Rocket | Upgrade::SawBlade => !self.has(Modifier::SawbladesOnly),
+            Upgrade::HeatSink | Upgrade::Coolant => self.has(Modifier::Overheat),
+            _ => true,
+        }
+    }
+
+    pub fn allows_skill_tree(&self) -> bool {
+        !self.has(Modifier::NoSkillTree)
+    }
+}
+
+// everything random about a run that should be repeatable from its seed, this is what `StdRng`
+// uses under the hood but its position can be saved with the run
+#[derive(Deref, DerefMut)]
+pub struct RunRng(pub ChaCha12Rng);
+
+impl Default for RunRng {
+    fn default() -> RunRng {
+        RunRng(ChaCha12Rng::from_entropy())
+    }
+}
+
+#[derive(Component)]
+struct MutatorMenu;
+
+#[derive(Component)]
+struct StartButton;
+
+#[derive(Component)]
+struct BackButton;
+
+pub struct ModifierPlugin;
+
+impl Plugin for ModifierPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunModifiers>()
+            .init_resource::<RunRng>()
+            .add_enter_system(GameState::Menu, clear_modifiers)
+            .add_enter_system(GameState::Playing, apply_modifiers.run_if(run_starting))
+            .add_enter_system(GameState::Mutators, spawn_mutator_menu)
+            .add_exit_system(GameState::Mutators, despawn_with::<MutatorMenu>)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Mutators)
+                    .with_system(update_buttons)
+                    .with_system(toggle_modifier)
+                    .into(),
+            )
+            .add_system(
+                start_run
+                    .run_in_state(GameState::Mutators)
+                    .run_if(button_pressed::<StartButton>),
+            )
+            .add_system(
+                to_menu
+                    .run_in_state(GameState::Mutators)
+                    .run_if(button_pressed::<BackButton>),
+            );
+    }
+}
+
+fn clear_modifiers(mut modifiers: ResMut<RunModifiers>, pkv: Res<PkvStore>) {
+    *modifiers = default();
+
+    // the picker starts out with the mutators of the last run
+    if let Ok(saved) = pkv.get::<Vec<Modifier>>("mutators") {
+        modifiers.modifiers = saved;
+    }
+}
+
+fn apply_modifiers(
+    modifiers: Res<RunModifiers>,
+    mut rng: ResMut<RunRng>,
+    mut spawn_timer: ResMut<SpawnTimer>,
+) {
+    let seed = modifiers.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    rng.0 = ChaCha12Rng::seed_from_u64(seed);
+
+    let duration = spawn_timer.duration();
+    spawn_timer.set_duration(duration.div_f32(modifiers.effects().spawn_rate));
+}
+
+fn spawn_mutator_menu(mut commands: Commands, fonts: Res<Fonts>, modifiers: Res<RunModifiers>) {
+    let text_style = TextStyle {
+        font: fonts.main.clone(),
+        font_size: 30.0,
+        color: Color::WHITE,
+    };
+
+    let button_style = Style {
+        size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+        margin: UiRect::all(Val::Px(6.0)),
+        align_items: AlignItems::Center,
+        justify_content: JustifyContent::Center,
+        ..default()
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            color: UiColor([0.0; 4].into()),
+            style: Style {
+                margin: UiRect::all(Val::Auto),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(MutatorMenu)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                format!("Mutators - Score x{:.2}", modifiers.effects().score),
+                TextStyle {
+                    color: Color::YELLOW,
+                    font_size: 50.0,
+                    ..text_style.clone()
+                },
+            ));
+
+            // two per row to fit them all on screen
+            for row in Modifier::ALL.chunks(2) {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        color: UiColor([0.0; 4].into()),
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        for &modifier in row {
+                            parent
+                                .spawn_bundle(ButtonBundle {
+                                    style: Style {
+                                        size: Size::new(Val::Px(480.0), Val::Px(80.0)),
+                                        flex_direction: FlexDirection::ColumnReverse,
+                                        ..button_style.clone()
+                                    },
+                                    ..default()
+                                })
+                                .insert(modifier)
+                                .with_children(|parent| {
+                                    parent.spawn_bundle(TextBundle::from_section(
+                                        format!(
+                                            "{} (x{:.2})",
+                                            modifier.name(),
+                                            modifier.effects().score
+                                        ),
+                                        TextStyle {
+                                            color: if modifiers.has(modifier) {
+                                                Color::GREEN
+                                            } else {
+                                                Color::WHITE
+                                            },
+                                            ..text_style.clone()
+                                        },
+                                    ));
+                                    parent.spawn_bundle(TextBundle::from_section(
+                                        modifier.description(),
+                                        TextStyle {
+                                            color: Color::GRAY,
+                                            font_size: 20.0,
+                                            ..text_style.clone()
+                                        },
+                                    ));
+                                });
+                        }
+                    });
+            }
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    color: UiColor([0.0; 4].into()),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: button_style.clone(),
+                            ..default()
+                        })
+                        .insert(BackButton)
+                        .with_children(|parent| {
+                            parent
+                                .spawn_bundle(TextBundle::from_section("Back", text_style.clone()));
+                        });
+
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: button_style.clone(),
+                            ..default()
+                        })
+                        .insert(StartButton)
+                        .with_children(|parent| {
+                            parent.spawn_bundle(TextBundle::from_section(
+                                "Start",
+                                text_style.clone(),
+                            ));
+                        });
+                });
+        });
+}
+
+fn toggle_modifier(
+    mut commands: Commands,
+    mut modifiers: ResMut<RunModifiers>,
+    buttons: Query<(&Interaction, &Modifier), (Changed<Interaction>, With<Button>)>,
+) {
+    for (interaction, modifier) in &buttons {
+        if *interaction == Interaction::Clicked {
+            if modifiers.has(*modifier) {
+                modifiers.modifiers.retain(|picked| picked != modifier);
+            } else {
+                modifiers.modifiers.push(*modifier);
+            }
+
+            // re-entering the state rebuilds the labels
+            commands.insert_resource(NextState(GameState::Mutators));
+        }
+    }
+}
+
+fn start_run(mut commands: Commands, modifiers: Res<RunModifiers>, mut pkv: ResMut<PkvStore>) {
+    pkv.set("mutators", &modifiers.modifiers)
+        .expect("failed to access pkv store");
+    commands.insert_resource(NextState(GameState::Playing));
+}
+
+fn to_menu(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Menu));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::BulletType;
+
+    fn run(modifiers: &[Modifier]) -> RunModifiers {
+        RunModifiers {
+            modifiers: modifiers.to_vec(),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn no_modifiers_change_nothing() {
+        let effects = run(&[]).effects();
+        assert_eq!(effects.damage, 1.0);
+        assert_eq!(effects.coin_value, 1);
+        assert_eq!(effects.score, 1.0);
+    }
+
+    #[test]
+    fn effects_multiply() {
+        let effects = ModifierEffects {
+            damage: 2.0,
+            coin_value: 2,
+            ..ModifierEffects::NONE
+        }
+        .stack(ModifierEffects {
+            damage: 1.5,
+            coin_value: 3,
+            score: 0.5,
+            ..ModifierEffects::NONE
+        });
+
+        assert_eq!(effects.damage, 3.0);
+        assert_eq!(effects.coin_value, 6);
+        assert_eq!(effects.score, 0.5);
+        assert_eq!(effects.player_health, 1.0);
+    }
+
+    #[test]
+    fn glass_cannon_and_swarm_stack() {
+        let effects = run(&[Modifier::GlassCannon, Modifier::Swarm]).effects();
+        assert_eq!(effects.player_health, 0.5);
+        assert_eq!(effects.damage, 2.0);
+        assert_eq!(effects.enemy_health, 0.5);
+        assert_eq!(effects.spawn_rate, 2.0);
+        assert_eq!(effects.score, 1.25 * 1.25);
+    }
+
+    #[test]
+    fn sawblades_only_starts_with_sawblades() {
+        let modifiers = run(&[Modifier::SawbladesOnly]);
+        assert!(matches!(
+            modifiers.base_loadout().bullet_type,
+            BulletType::SawBlade
+        ));
+        assert!(!modifiers.allows(Upgrade::Rocket));
+        assert!(modifiers.allows(Upgrade::Crit1));
+    }
+}
@@ -0,0 +1,222 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use rand::{thread_rng, Rng};
+
+use crate::{despawn_with, GameState};
+
+// past this many live particles new bursts are dropped, so a high fire rate can't bog things down
+const MAX_PARTICLES: usize = 600;
+
+#[derive(Clone, Copy)]
+pub enum ParticleKind {
+    Blood,
+    Sparks,
+    Sparkle,
+    MuzzleFlash,
+}
+
+impl ParticleKind {
+    fn color(&self) -> Color {
+        match self {
+            ParticleKind::Blood => Color::rgb(0.55, 0.05, 0.05),
+            ParticleKind::Sparks => Color::rgb(1.0, 0.6, 0.15),
+            ParticleKind::Sparkle => Color::rgb(1.0, 0.95, 0.3),
+            ParticleKind::MuzzleFlash => Color::rgb(1.0, 0.9, 0.6),
+        }
+    }
+
+    // count, speed, size, lifetime
+    fn stats(&self) -> (u32, f32, f32, f32) {
+        match self {
+            ParticleKind::Blood => (8, 250.0, 6.0, 0.4),
+            ParticleKind::Sparks => (14, 500.0, 4.0, 0.3),
+            ParticleKind::Sparkle => (6, 120.0, 5.0, 0.5),
+            ParticleKind::MuzzleFlash => (5, 350.0, 8.0, 0.08),
+        }
+    }
+}
+
+pub struct ParticleEvent {
+    pub position: Vec2,
+    pub kind: ParticleKind,
+    // particles fly out in a cone around this, or in every direction if it's None
+    pub direction: Option<Vec2>,
+}
+
+#[derive(Component)]
+pub struct Particle {
+    velocity: Vec2,
+    lifetime: Timer,
+    size: f32,
+}
+
+// tints the sprite for a moment after being hit, then puts back the colour it had before
+#[derive(Component)]
+pub struct HitFlash {
+    timer: Timer,
+    previous: Option<Color>,
+}
+
+impl HitFlash {
+    pub fn restart(&mut self) {
+        self.timer.reset();
+    }
+
+    // for anything else that tints sprites, so the end of a flash doesn't undo it
+    pub fn tint(sprite: &mut Sprite, flash: Option<Mut<HitFlash>>, color: Color) {
+        match flash {
+            Some(mut flash) => flash.previous = Some(color),
+            None => sprite.color = color,
+        }
+    }
+}
+
+impl Default for HitFlash {
+    fn default() -> HitFlash {
+        HitFlash {
+            timer: Timer::from_seconds(0.1, false),
+            previous: None,
+        }
+    }
+}
+
+// a leftover sprite that shrinks and fades away after an enemy dies
+#[derive(Component)]
+pub struct Dying {
+    timer: Timer,
+    scale: Vec3,
+}
+
+impl Dying {
+    pub fn new(scale: Vec3) -> Self {
+        Self {
+            timer: Timer::from_seconds(0.3, false),
+            scale,
+        }
+    }
+}
+
+pub struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ParticleEvent>()
+            .add_enter_system(GameState::GameOver, despawn_with::<Particle>)
+            .add_enter_system(GameState::GameOver, despawn_with::<Dying>)
+            .add_enter_system(GameState::Menu, despawn_with::<Particle>)
+            .add_enter_system(GameState::Menu, despawn_with::<Dying>)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Playing)
+                    .with_system(spawn_particles)
+                    .with_system(update_particles)
+                    .with_system(flash_on_hit)
+                    .with_system(animate_dying)
+                    .into(),
+            );
+    }
+}
+
+fn spawn_particles(
+    mut commands: Commands,
+    mut events: EventReader<ParticleEvent>,
+    particles: Query<(), With<Particle>>,
+) {
+    let mut rng = thread_rng();
+    let mut count = particles.iter().count();
+
+    for ev in events.iter() {
+        let (amount, speed, size, lifetime) = ev.kind.stats();
+
+        if count + amount as usize > MAX_PARTICLES {
+            continue;
+        }
+        count += amount as usize;
+
+        for _ in 0..amount {
+            let dir = match ev.direction {
+                Some(dir) => Vec2::from_angle(rng.gen_range(-PI / 6.0..PI / 6.0)).rotate(dir),
+                None => Vec2::from_angle(rng.gen_range(-PI..PI)),
+            }
+            .normalize_or_zero();
+
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: ev.kind.color(),
+                        custom_size: Some(Vec2::splat(size)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(ev.position.extend(20.0)),
+                    ..default()
+                })
+                .insert(Particle {
+                    velocity: dir * speed * rng.gen_range(0.5..1.0),
+                    lifetime: Timer::from_seconds(lifetime * rng.gen_range(0.7..1.0), false),
+                    size,
+                });
+        }
+    }
+}
+
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut Transform, &mut Sprite, &mut Particle)>,
+) {
+    for (entity, mut transform, mut sprite, mut particle) in &mut particles {
+        particle.lifetime.tick(time.delta());
+
+        if particle.lifetime.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        transform.translation += (particle.velocity * time.delta_seconds()).extend(0.0);
+        particle.velocity *= 1.0 - 4.0 * time.delta_seconds().min(0.25);
+
+        let remaining = 1.0 - particle.lifetime.percent();
+        sprite.custom_size = Some(Vec2::splat(particle.size * remaining.max(0.3)));
+        sprite.color.set_a(remaining);
+    }
+}
+
+fn flash_on_hit(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut flashing: Query<(Entity, &mut Sprite, &mut HitFlash)>,
+) {
+    for (entity, mut sprite, mut flash) in &mut flashing {
+        let previous = *flash.previous.get_or_insert(sprite.color);
+        flash.timer.tick(time.delta());
+
+        if flash.timer.finished() {
+            sprite.color = previous;
+            commands.entity(entity).remove::<HitFlash>();
+        } else {
+            sprite.color = Color::rgb(1.0, 0.35, 0.35);
+        }
+    }
+}
+
+fn animate_dying(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut dying: Query<(Entity, &mut Transform, &mut Sprite, &mut Dying)>,
+) {
+    for (entity, mut transform, mut sprite, mut dying) in &mut dying {
+        dying.timer.tick(time.delta());
+
+        if dying.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let remaining = 1.0 - dying.timer.percent();
+        transform.scale = dying.scale * remaining;
+        transform.rotate(Quat::from_rotation_z(10.0 * time.delta_seconds()));
+        sprite.color = Color::rgba(1.0, 0.3, 0.3, remaining);
+    }
+}
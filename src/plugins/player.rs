@@ -1,39 +1,67 @@
 use std::{f32::consts::PI, time::Duration};
 
-use bevy::{prelude::*, utils::HashSet};
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashSet};
 use bevy_rapier2d::prelude::*;
 use iyes_loopless::prelude::*;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use crate::{
     components::{
-        Barrel, Bullet, Coin, Damage, Enemy, Health, HitEnemies, Knockback, Pierce, Player, Ready,
+        Aim, Barrel, Boomerang, Bullet, BulletType, Charge, Controls, Crit, Damage, Downed, Enemy,
+        Frost, Health, Heat, HitEnemies, Homing, Knockback, Leech, Loadout, Overdrive, Owner,
+        Pierce, Player, Ready, RehitCooldown, Ricochet, ShootTimer, Spin, Stunned, Volley,
+        WeaponBundle,
     },
-    resources::{BulletType, Coins, Fonts, MousePosition, ShootTimer, Sounds, Spread, Sprites},
-    GameState,
+    resources::{
+        Coins, Difficulty, MetaProgress, MousePosition, Score, Settings, Sounds, Sprites,
+        ARENA_SIZE,
+    },
+    run_starting, AbilitySlots, Buffs, DamageEvent, DamageKind, GameState, HitEvent, HitFlash,
+    Modifier, ParticleEvent, ParticleKind, PowerUp, RunModifiers, RunRng, ShakeEvent, ShotEvent,
+    Respec, ResumeRun, Upgrades, BULLET_GROUP, OVERDRIVE_SPEEDUP, RAPID_FIRE_SPEEDUP, TANK_GROUP, TRIPLE_SHOT_EXTRA,
 };
 
+// homing bullets only pick targets within this angle of where they're heading
+const HOMING_CONE: f32 = PI / 3.0;
+
+// how far the stick has to be pushed before a gamepad tank turns
+const STICK_DEADZONE: f32 = 0.3;
+
+// the barrel flips between the first two frames while firing, the last one is only for overheating
+const BARREL_FIRING_FRAMES: usize = 2;
+const BARREL_OVERHEATED: usize = 2;
+
+// heat added by every volley, the loadout decides how much the barrel holds
+const HEAT_PER_VOLLEY: f32 = 8.0;
+
+// what a full charge adds on top of a volley, partial charges add a share of it
+const CHARGE_DAMAGE: f32 = 2.0;
+const CHARGE_KNOCKBACK: f32 = 2.0;
+const CHARGE_PIERCE: f32 = 3.0;
+const CHARGE_SIZE: f32 = 1.0;
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<MousePosition>()
             .init_resource::<Coins>()
-            .init_resource::<Spread>()
-            .init_resource::<BulletType>()
-            .init_resource::<ShootTimer>()
-            .init_resource::<Knockback>()
-            .init_resource::<Pierce>()
-            .init_resource::<Damage>()
-            .add_exit_system(GameState::Menu, spawn_player)
+            .add_enter_system(GameState::Playing, spawn_player.run_if(run_starting))
+            .add_system(sync_fire_interval)
             .add_system_set(
                 ConditionSet::new()
                     .run_in_state(GameState::Playing)
                     .with_system(update_mouse_position)
+                    .with_system(aim_tanks)
                     .with_system(shoot)
                     .with_system(rotate_player)
                     .with_system(animate_player)
+                    .with_system(cool_barrels)
                     .with_system(collide_bullets)
+                    .with_system(ricochet_bullets)
+                    .with_system(return_boomerangs)
+                    .with_system(spin_bullets)
+                    .with_system(home_bullets)
                     .with_system(despawn_offscreen)
                     .with_system(handle_player_death)
                     .into(),
@@ -41,188 +69,603 @@ impl Plugin for PlayerPlugin {
     }
 }
 
-fn spawn_player(mut commands: Commands, fonts: Res<Fonts>, sprites: Res<Sprites>) {
-    commands
-        .spawn_bundle(SpriteBundle {
-            transform: Transform::from_scale(Vec3::splat(5.))
-                .with_rotation(Quat::from_rotation_z(PI / 2.0)),
-            texture: sprites.base.clone(),
-            ..default()
-        })
-        .insert(Player)
-        .insert(Health::new(200.0))
-        .insert(RigidBody::Fixed)
-        .insert(Collider::cuboid(7., 7.))
-        .insert(LockedAxes::TRANSLATION_LOCKED)
-        .insert(ActiveEvents::COLLISION_EVENTS)
-        .with_children(|parent| {
-            parent
-                .spawn_bundle(SpriteSheetBundle {
-                    texture_atlas: sprites.barrel.clone(),
-                    transform: Transform::from_translation(Vec3::Z * 10.0),
-                    ..default()
-                })
-                .insert(Barrel)
-                .insert(Ready(false));
-        });
-
-    commands
-        .spawn_bundle(NodeBundle {
-            color: UiColor([0.0; 4].into()),
-            style: Style {
-                position_type: PositionType::Absolute,
-                size: Size::new(Val::Px(300.0), Val::Px(20.0)),
-                margin: UiRect {
-                    bottom: Val::Auto,
-                    ..default()
-                },
-                position: UiRect {
-                    top: Val::Px(15.0),
-                    left: Val::Px(130.0),
-                    ..default()
-                },
-                justify_content: JustifyContent::FlexStart,
-                align_items: AlignItems::Center,
-                ..default()
-            },
-            ..default()
-        })
-        .with_children(|parent| {
-            parent.spawn_bundle(ImageBundle {
-                style: Style {
-                    size: Size::new(Val::Px(20.0), Val::Px(20.0)),
-                    margin: UiRect::all(Val::Px(10.0)),
-                    ..default()
-                },
-                image: UiImage(sprites.coin.clone()),
+// the buttons of every tank, whether it's played with the mouse or a gamepad
+#[derive(SystemParam)]
+pub struct TankInput<'w, 's> {
+    keys: Res<'w, Input<KeyCode>>,
+    mouse: Res<'w, Input<MouseButton>>,
+    buttons: Res<'w, Input<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+    #[system_param(ignore)]
+    _marker: std::marker::PhantomData<&'s ()>,
+}
+
+impl<'w, 's> TankInput<'w, 's> {
+    pub fn fire_pressed(&self, controls: Controls) -> bool {
+        match controls {
+            Controls::Mouse => self.mouse.pressed(MouseButton::Left) && !self.shift(),
+            Controls::Gamepad(gamepad) => self.buttons.pressed(GamepadButton::new(
+                gamepad,
+                GamepadButtonType::RightTrigger2,
+            )),
+        }
+    }
+
+    pub fn fire_just_pressed(&self, controls: Controls) -> bool {
+        match controls {
+            Controls::Mouse => self.mouse.just_pressed(MouseButton::Left) && !self.shift(),
+            Controls::Gamepad(gamepad) => self.buttons.just_pressed(GamepadButton::new(
+                gamepad,
+                GamepadButtonType::RightTrigger2,
+            )),
+        }
+    }
+
+    pub fn toggle_tree(&self, controls: Controls) -> bool {
+        match controls {
+            Controls::Mouse => self
+                .mouse
+                .any_just_pressed([MouseButton::Right, MouseButton::Middle]),
+            Controls::Gamepad(gamepad) => self
+                .buttons
+                .just_pressed(GamepadButton::new(gamepad, GamepadButtonType::North)),
+        }
+    }
+
+    pub fn ability_just_pressed(&self, controls: Controls, slot: usize) -> bool {
+        match controls {
+            Controls::Mouse => self
+                .keys
+                .just_pressed([KeyCode::Q, KeyCode::E, KeyCode::R, KeyCode::F][slot]),
+            Controls::Gamepad(gamepad) => self.buttons.just_pressed(GamepadButton::new(
+                gamepad,
+                [
+                    GamepadButtonType::South,
+                    GamepadButtonType::East,
+                    GamepadButtonType::LeftTrigger,
+                    GamepadButtonType::RightTrigger,
+                ][slot],
+            )),
+        }
+    }
+
+    // shift + click doesn't fire, so placing a turret doesn't waste a shot
+    pub fn place_turret(&self, controls: Controls) -> bool {
+        match controls {
+            Controls::Mouse => self.mouse.just_pressed(MouseButton::Left) && self.shift(),
+            Controls::Gamepad(gamepad) => self
+                .buttons
+                .just_pressed(GamepadButton::new(gamepad, GamepadButtonType::West)),
+        }
+    }
+
+    // the mouse can point at nodes in the skill tree, gamepads step through them with the d-pad
+    pub fn tree_step(&self, controls: Controls) -> IVec2 {
+        let gamepad = if let Controls::Gamepad(gamepad) = controls {
+            gamepad
+        } else {
+            return IVec2::ZERO;
+        };
+        let pressed = |button_type| {
+            self.buttons
+                .just_pressed(GamepadButton::new(gamepad, button_type)) as i32
+        };
+
+        IVec2::new(
+            pressed(GamepadButtonType::DPadRight) - pressed(GamepadButtonType::DPadLeft),
+            pressed(GamepadButtonType::DPadUp) - pressed(GamepadButtonType::DPadDown),
+        )
+    }
+
+    pub fn tree_buy(&self, controls: Controls) -> bool {
+        match controls {
+            Controls::Mouse => false,
+            Controls::Gamepad(gamepad) => self
+                .buttons
+                .just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South)),
+        }
+    }
+
+    pub fn tree_respec(&self, controls: Controls) -> bool {
+        match controls {
+            Controls::Mouse => false,
+            Controls::Gamepad(gamepad) => self
+                .buttons
+                .just_pressed(GamepadButton::new(gamepad, GamepadButtonType::West)),
+        }
+    }
+
+    fn shift(&self) -> bool {
+        self.keys.any_pressed([KeyCode::LShift, KeyCode::RShift])
+    }
+
+    fn stick(&self, gamepad: Gamepad) -> Vec2 {
+        let axis = |axis_type| {
+            self.axes
+                .get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+
+        Vec2::new(
+            axis(GamepadAxisType::RightStickX),
+            axis(GamepadAxisType::RightStickY),
+        )
+    }
+}
+
+// the closest of the given tanks to a position
+pub fn nearest_tank<'a>(
+    tanks: impl IntoIterator<Item = &'a Transform>,
+    position: Vec2,
+) -> Option<Vec2> {
+    tanks
+        .into_iter()
+        .map(|transform| transform.translation.truncate())
+        .min_by(|a, b| a.distance(position).total_cmp(&b.distance(position)))
+}
+
+fn spawn_player(
+    mut commands: Commands,
+    sprites: Res<Sprites>,
+    meta: Res<MetaProgress>,
+    difficulty: Res<Difficulty>,
+    modifiers: Res<RunModifiers>,
+    settings: Res<Settings>,
+    resume: Option<Res<ResumeRun>>,
+    gamepads: Res<Gamepads>,
+    mut coins: ResMut<Coins>,
+) {
+    coins.0 = meta.starting_coins();
+
+    let co_op = resume.map_or(settings.co_op, |resume| resume.tanks() > 1);
+
+    // the second tank takes the first gamepad, a controller plugged in later shows up as 0 too
+    let mut tanks = vec![(Controls::Mouse, Vec3::ZERO)];
+    if co_op {
+        let gamepad = gamepads
+            .iter()
+            .next()
+            .copied()
+            .unwrap_or_else(|| Gamepad::new(0));
+        tanks = vec![
+            (Controls::Mouse, Vec3::new(-150.0, 0.0, 0.0)),
+            (Controls::Gamepad(gamepad), Vec3::new(150.0, 0.0, 0.0)),
+        ];
+    }
+
+    let loadout = Upgrades::default().loadout(&modifiers);
+
+    for (index, (controls, translation)) in tanks.into_iter().enumerate() {
+        let tank = commands
+            .spawn_bundle(SpriteBundle {
+                transform: Transform::from_translation(translation)
+                    .with_scale(Vec3::splat(5.))
+                    .with_rotation(Quat::from_rotation_z(PI / 2.0)),
+                texture: sprites.base.clone(),
                 ..default()
-            });
-            parent
-                .spawn_bundle(TextBundle::from_section(
-                    "0",
-                    TextStyle {
-                        color: Color::YELLOW,
-                        font: fonts.main.clone(),
-                        font_size: 30.0,
-                    },
-                ))
-                .insert(Coin);
-        });
+            })
+            .insert(Player(index))
+            .insert(controls)
+            .insert(Aim(Vec2::Y))
+            .insert(Health::new(
+                (difficulty.player_health + meta.bonus_health())
+                    * modifiers.effects().player_health,
+            ))
+            .insert(Upgrades::default())
+            .insert(Respec::default())
+            .insert(AbilitySlots::default())
+            .insert(Buffs::default())
+            .insert(Charge::default())
+            .insert_bundle(WeaponBundle::new(loadout.clone()))
+            .insert(RigidBody::Fixed)
+            .insert(Collider::cuboid(7., 7.))
+            .insert(LockedAxes::TRANSLATION_LOCKED)
+            .insert(ActiveEvents::COLLISION_EVENTS)
+            .insert(CollisionGroups::new(TANK_GROUP, u32::MAX))
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(SpriteSheetBundle {
+                        texture_atlas: sprites.barrel.clone(),
+                        transform: Transform::from_translation(Vec3::Z * 10.0),
+                        ..default()
+                    })
+                    .insert(Barrel)
+                    .insert(Ready(false));
+            })
+            .id();
+
+        if modifiers.has(Modifier::Overheat) {
+            commands.entity(tank).insert(Heat::default());
+        }
+    }
+}
+
+fn aim_tanks(
+    mouse_pos: Res<MousePosition>,
+    input: TankInput,
+    mut tanks: Query<(&Transform, &Controls, &mut Aim), (With<Player>, Without<Downed>)>,
+) {
+    for (transform, controls, mut aim) in &mut tanks {
+        let dir = match controls {
+            Controls::Mouse => (mouse_pos.0 - transform.translation.truncate()).normalize_or_zero(),
+            Controls::Gamepad(gamepad) => {
+                let stick = input.stick(*gamepad);
+                if stick.length() > STICK_DEADZONE {
+                    stick.normalize()
+                } else {
+                    Vec2::ZERO
+                }
+            }
+        };
+
+        // a centered stick keeps the last direction
+        if dir != Vec2::ZERO {
+            aim.0 = dir;
+        }
+    }
 }
 
 fn shoot(
     mut commands: Commands,
-    mouse_buttons: Res<Input<MouseButton>>,
+    time: Res<Time>,
+    input: TankInput,
     sprites: Res<Sprites>,
-    mouse_pos: Res<MousePosition>,
-    knockback: Res<Knockback>,
-    bullet_type: Res<BulletType>,
-    spread: Res<Spread>,
-    damage: Res<Damage>,
-    pierce: Res<Pierce>,
-    mut timer: ResMut<ShootTimer>,
-    mut player: Query<&Transform, With<Player>>,
-    mut barrel: Query<(&mut TextureAtlasSprite, &mut Ready), With<Barrel>>,
+    mut rng: ResMut<RunRng>,
+    mut tanks: Query<
+        (
+            Entity,
+            &Transform,
+            &Controls,
+            &Aim,
+            &Loadout,
+            &mut ShootTimer,
+            &mut Volley,
+            &mut Charge,
+            &Buffs,
+            Option<&mut Heat>,
+            &Children,
+        ),
+        (With<Player>, Without<Downed>),
+    >,
+    mut barrels: Query<(&mut TextureAtlasSprite, &mut Ready), With<Barrel>>,
     audio: Res<Audio>,
     sound: Res<Sounds>,
+    mut particle_events: EventWriter<ParticleEvent>,
+    mut shot_events: EventWriter<ShotEvent>,
 ) {
-    let transform = player.single_mut();
-    let (mut sprite, mut ready) = barrel.single_mut();
+    for (
+        tank,
+        transform,
+        controls,
+        aim,
+        loadout,
+        mut timer,
+        mut volley,
+        mut charge,
+        buffs,
+        mut heat,
+        children,
+    ) in &mut tanks
+    {
+        let barrel = children
+            .iter()
+            .copied()
+            .find(|child| barrels.get(*child).is_ok());
+        let (mut sprite, mut ready) = if let Some(Ok(barrel)) = barrel.map(|b| barrels.get_mut(b)) {
+            barrel
+        } else {
+            continue;
+        };
+        let mut pattern = loadout.pattern.clone();
+        if buffs.has(PowerUp::TripleShot) {
+            pattern.shots += TRIPLE_SHOT_EXTRA;
+        }
 
-    if mouse_buttons.just_pressed(MouseButton::Left) {
-        ready.0 = false;
-        if timer.paused() {
-            timer.unpause();
-            sprite.index = 1;
+        // an overheated barrel stays locked until `cool_barrels` lets go of it
+        if heat.as_ref().map_or(false, |heat| heat.overheated) {
+            continue;
         }
-    }
 
-    let mut rng = thread_rng();
+        // every tick of the shoot timer starts a burst, the rest of its volleys follow on their own,
+        // a charged barrel doesn't tick but starts its burst when the trigger is let go
+        let started = if let Some(charge_time) = loadout.charge_time {
+            if input.fire_pressed(*controls) {
+                let was_full = charge.0 >= 1.0;
+                charge.0 = (charge.0 + time.delta_seconds() / charge_time).min(1.0);
 
-    if timer.just_finished() && !timer.paused() && sprite.index == 0 {
-        let dir = Vec2::from_angle(rng.gen_range(-spread.1..=spread.1))
-            .rotate(mouse_pos.0 - transform.translation.truncate())
-            .normalize();
+                if charge.0 >= 1.0 && !was_full {
+                    particle_events.send(ParticleEvent {
+                        position: transform.translation.truncate() + aim.0 * 60.0,
+                        kind: ParticleKind::Sparkle,
+                        direction: None,
+                    });
+                }
 
-        audio.play_with_settings(
-            sound.shoot.clone(),
-            PlaybackSettings::ONCE.with_volume(0.05),
-        );
+                false
+            } else {
+                // the trigger has to be held for at least a fire interval, so tapping it can't
+                // fire faster than an uncharged barrel, shorter holds fizzle out
+                let released = charge.0 >= loadout.fire_interval / charge_time;
+                if !released {
+                    charge.0 = 0.0;
+                }
+
+                released
+            }
+        } else {
+            charge.0 = 0.0;
 
-        let scale = match *bullet_type {
-            BulletType::Regular => Vec3::splat(1.5),
-            BulletType::Rocket => Vec3::splat(2.5),
-            BulletType::SawBlade => Vec3::splat(2.0),
+            if input.fire_just_pressed(*controls) {
+                ready.0 = false;
+                if timer.paused() {
+                    timer.unpause();
+                    sprite.index = 1;
+                }
+            }
+
+            timer.just_finished() && !timer.paused() && sprite.index == 0
         };
 
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: match *bullet_type {
-                    BulletType::Regular => sprites.bullet.clone(),
-                    BulletType::Rocket => sprites.rocket.clone(),
-                    BulletType::SawBlade => sprites.saw_blade.clone(),
-                },
-                transform: Transform::from_translation(transform.translation)
-                    .with_scale(scale)
-                    .with_rotation(Quat::from_rotation_z(Vec2::X.angle_between(dir))),
-                ..default()
-            })
-            .insert(Bullet)
-            .insert(pierce.clone())
-            .insert(damage.clone())
-            .insert(knockback.clone())
-            .insert(HitEnemies::default())
-            .insert(RigidBody::Dynamic)
-            .insert(Ccd::enabled())
-            .insert(Velocity::linear(dir * 1500.0))
-            .insert(Collider::cuboid(8.0, 8.0))
-            .insert(Sensor)
-            .insert(ActiveEvents::COLLISION_EVENTS);
-    }
-
-    if !mouse_buttons.pressed(MouseButton::Left) && sprite.index == 0 {
-        ready.0 = true;
+        sprite.color = charge_color(charge.0, &time);
+
+        let fire = if started {
+            volley.shots_left = pattern.burst;
+            volley.timer = Timer::from_seconds(pattern.burst_delay, true);
+            volley.charge = std::mem::take(&mut charge.0);
+
+            // charged shots still kick the barrel back once
+            if loadout.charge_time.is_some() {
+                ready.0 = false;
+                timer.unpause();
+                sprite.index = 1;
+            }
+
+            true
+        } else if volley.shots_left > 0 {
+            volley.timer.tick(time.delta());
+            volley.timer.just_finished()
+        } else {
+            false
+        };
+
+        if fire {
+            volley.shots_left -= 1;
+            volley.left_barrel = !volley.left_barrel;
+
+            let aim = aim.0;
+            let loadout = &charged(loadout, volley.charge);
+
+            let origin = if pattern.twin {
+                let side = if volley.left_barrel { 1.0 } else { -1.0 };
+                transform.translation + (aim.perp() * side * 20.0).extend(0.0)
+            } else {
+                transform.translation
+            };
+
+            audio.play_with_settings(
+                sound.shoot.clone(),
+                PlaybackSettings::ONCE.with_volume(0.05),
+            );
+
+            particle_events.send(ParticleEvent {
+                position: origin.truncate() + aim * 60.0,
+                kind: ParticleKind::MuzzleFlash,
+                direction: Some(aim),
+            });
+
+            for dir in pattern.directions(aim) {
+                let spread = loadout.spread.1;
+                let dir = Vec2::from_angle(rng.gen_range(-spread..=spread)).rotate(dir);
+
+                spawn_bullet(&mut commands, &sprites, loadout, tank, origin, dir);
+                shot_events.send(ShotEvent);
+            }
+
+            if let Some(heat) = &mut heat {
+                heat.value += HEAT_PER_VOLLEY * (1.0 + volley.charge);
+                if heat.value >= loadout.heat_capacity {
+                    heat.overheated = true;
+                    volley.shots_left = 0;
+                    timer.pause();
+                    sprite.index = BARREL_OVERHEATED;
+                }
+            }
+        }
+
+        if !input.fire_pressed(*controls) && sprite.index == 0 {
+            ready.0 = true;
+        }
+    }
+}
+
+// the loadout of a volley fired with the given charge
+fn charged(loadout: &Loadout, charge: f32) -> Loadout {
+    let mut loadout = loadout.clone();
+    loadout.damage.0 *= 1.0 + CHARGE_DAMAGE * charge;
+    loadout.knockback.0 *= 1.0 + CHARGE_KNOCKBACK * charge;
+    loadout.pierce.0 += (CHARGE_PIERCE * charge).round() as i32;
+    loadout.bullet_size *= 1.0 + CHARGE_SIZE * charge;
+    loadout
+}
+
+// the barrel glows brighter the further it's charged and flickers once it's full
+fn charge_color(charge: f32, time: &Time) -> Color {
+    if charge >= 1.0 && (time.seconds_since_startup() * 12.0) as u32 % 2 == 0 {
+        return Color::WHITE;
+    }
+
+    Color::rgb(1.0 - charge, 1.0, 1.0)
+}
+
+// the shoot timer follows the loadout's fire interval, sped up by overdrive and rapid fire
+fn sync_fire_interval(
+    mut shooters: Query<(
+        &Loadout,
+        &mut ShootTimer,
+        Option<&Overdrive>,
+        Option<&Buffs>,
+    )>,
+) {
+    for (loadout, mut timer, overdrive, buffs) in &mut shooters {
+        let mut interval = loadout.fire_interval;
+        if overdrive.is_some() {
+            interval /= OVERDRIVE_SPEEDUP;
+        }
+        if buffs.map_or(false, |buffs| buffs.has(PowerUp::RapidFire)) {
+            interval /= RAPID_FIRE_SPEEDUP;
+        }
+
+        let interval = Duration::from_secs_f32(interval);
+        if timer.duration() != interval {
+            timer.set_duration(interval);
+        }
+    }
+}
+
+pub fn spawn_bullet(
+    commands: &mut Commands,
+    sprites: &Sprites,
+    loadout: &Loadout,
+    owner: Entity,
+    origin: Vec3,
+    dir: Vec2,
+) -> Entity {
+    let scale = match loadout.bullet_type {
+        BulletType::Regular => Vec3::splat(1.5),
+        BulletType::Rocket => Vec3::splat(2.5),
+        BulletType::SawBlade => Vec3::splat(2.0),
+    } * loadout.bullet_size;
+
+    let mut bullet = commands.spawn_bundle(SpriteBundle {
+        texture: match loadout.bullet_type {
+            BulletType::Regular => sprites.bullet.clone(),
+            BulletType::Rocket => sprites.rocket.clone(),
+            BulletType::SawBlade => sprites.saw_blade.clone(),
+        },
+        transform: Transform::from_translation(origin)
+            .with_scale(scale)
+            .with_rotation(Quat::from_rotation_z(Vec2::X.angle_between(dir))),
+        ..default()
+    });
+
+    bullet
+        .insert(Bullet)
+        .insert(Owner(owner))
+        .insert(loadout.bullet_type)
+        .insert(loadout.pierce.clone())
+        .insert(loadout.damage.clone())
+        .insert(loadout.crit.clone())
+        .insert(loadout.knockback.clone())
+        .insert(HitEnemies::default())
+        .insert(RigidBody::Dynamic)
+        .insert(Ccd::enabled())
+        .insert(Velocity::linear(dir * 1500.0))
+        .insert(Collider::cuboid(8.0, 8.0))
+        .insert(Sensor)
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(CollisionGroups::new(BULLET_GROUP, u32::MAX));
+
+    if loadout.homing.turn_rate > 0.0 {
+        bullet.insert(loadout.homing.clone());
+    }
+
+    if loadout.frost > 0.0 {
+        bullet.insert(Frost(loadout.frost));
+    }
+
+    if loadout.leech > 0.0 {
+        bullet.insert(Leech(loadout.leech));
+    }
+
+    if let BulletType::SawBlade = loadout.bullet_type {
+        let saw_blade = &loadout.saw_blade;
+        bullet
+            .insert(Ricochet(saw_blade.bounces))
+            .insert(RehitCooldown(saw_blade.rehit_cooldown))
+            .insert(Spin(-20.0));
+
+        if saw_blade.boomerang {
+            bullet.insert(Boomerang(Timer::from_seconds(0.75, false)));
+        }
     }
+
+    bullet.id()
 }
 
 fn animate_player(
     time: Res<Time>,
-    texture_atlases: Res<Assets<TextureAtlas>>,
-    mut anim_timer: ResMut<ShootTimer>,
-    mut barrel: Query<(&Handle<TextureAtlas>, &mut TextureAtlasSprite, &mut Ready), With<Barrel>>,
+    mut tanks: Query<(&mut ShootTimer, &Children, Option<&Heat>), With<Player>>,
+    mut barrels: Query<(&mut TextureAtlasSprite, &mut Ready), With<Barrel>>,
 ) {
-    let (atlas_handle, mut sprite, mut ready) = barrel.single_mut();
+    for (mut anim_timer, children, heat) in &mut tanks {
+        // the overheated frame stays up until the barrel has cooled down
+        if heat.map_or(false, |heat| heat.overheated) {
+            continue;
+        }
 
-    if anim_timer.paused() {
-        anim_timer.set_elapsed(Duration::ZERO);
-        sprite.index = 0;
-    } else {
-        anim_timer.tick(time.delta());
+        for child in children {
+            let (mut sprite, mut ready) = if let Ok(barrel) = barrels.get_mut(*child) {
+                barrel
+            } else {
+                continue;
+            };
 
-        if anim_timer.just_finished() {
-            let texture_atlas = texture_atlases.get(atlas_handle).unwrap();
+            if anim_timer.paused() {
+                anim_timer.set_elapsed(Duration::ZERO);
+                sprite.index = 0;
+            } else {
+                anim_timer.tick(time.delta());
 
-            sprite.index = (sprite.index + 1) % texture_atlas.len();
+                if anim_timer.just_finished() {
+                    sprite.index = (sprite.index + 1) % BARREL_FIRING_FRAMES;
 
-            if ready.0 && sprite.index == 1 {
-                sprite.index = 0;
-                anim_timer.pause();
-                ready.0 = false;
+                    if ready.0 && sprite.index == 1 {
+                        sprite.index = 0;
+                        anim_timer.pause();
+                        ready.0 = false;
+                    }
+                }
             }
         }
     }
 }
 
-fn rotate_player(
-    mouse_pos: Res<MousePosition>,
-    mut barrel: Query<(&mut Transform, &mut TextureAtlasSprite), With<Barrel>>,
+// barrels cool down all the time, an overheated one unlocks once it's completely cold
+fn cool_barrels(
+    time: Res<Time>,
+    mut tanks: Query<(&Loadout, &mut Heat, &Children)>,
+    mut barrels: Query<&mut TextureAtlasSprite, With<Barrel>>,
 ) {
-    let (mut player_transform, mut sprite) = barrel.single_mut();
+    for (loadout, mut heat, children) in &mut tanks {
+        heat.value = (heat.value - loadout.cooling * time.delta_seconds()).max(0.0);
+
+        if heat.overheated && heat.value == 0.0 {
+            heat.overheated = false;
 
-    // normalized vector pointing from player to mouse
-    let dir = (mouse_pos.0 - player_transform.translation.truncate()).normalize();
+            for child in children {
+                if let Ok(mut sprite) = barrels.get_mut(*child) {
+                    sprite.index = 0;
+                }
+            }
+        }
+    }
+}
+
+fn rotate_player(
+    tanks: Query<(&Aim, &Children), With<Player>>,
+    mut barrels: Query<(&mut Transform, &mut TextureAtlasSprite), With<Barrel>>,
+) {
+    for (aim, children) in &tanks {
+        for child in children {
+            if let Ok((mut transform, mut sprite)) = barrels.get_mut(*child) {
+                point_barrel(&mut transform, &mut sprite, aim.0);
+            }
+        }
+    }
+}
 
-    player_transform.rotation = Quat::from_rotation_z(
+pub fn point_barrel(transform: &mut Transform, sprite: &mut TextureAtlasSprite, dir: Vec2) {
+    transform.rotation = Quat::from_rotation_z(
         if dir.x < 0.0 {
             sprite.flip_x = false;
             sprite.flip_y = false;
@@ -237,73 +680,355 @@ fn rotate_player(
 
 fn collide_bullets(
     mut commands: Commands,
-    mut bullets: Query<(Entity, &mut HitEnemies, &mut Pierce, &Damage, &Knockback), With<Bullet>>,
-    mut enemies: Query<(Entity, &ExternalForce, &mut Health, &mut ExternalImpulse), With<Enemy>>,
-    bullet_type: Res<BulletType>,
+    time: Res<Time>,
+    mut bullets: Query<
+        (
+            Entity,
+            &mut HitEnemies,
+            &mut Pierce,
+            &Damage,
+            &Crit,
+            &Knockback,
+            &Velocity,
+            &BulletType,
+            Option<&RehitCooldown>,
+            &Owner,
+            Option<&Frost>,
+            Option<&Leech>,
+        ),
+        With<Bullet>,
+    >,
+    mut enemies: Query<
+        (
+            Entity,
+            &Transform,
+            &mut Health,
+            &mut ExternalImpulse,
+            Option<&Stunned>,
+        ),
+        With<Enemy>,
+    >,
+    mut tanks: Query<&mut Health, (With<Player>, Without<Downed>, Without<Enemy>)>,
+    rehitting: Query<(Entity, &RapierColliderHandle), (With<Bullet>, With<RehitCooldown>)>,
+    rapier_context: Res<RapierContext>,
+    mut flashes: Query<&mut HitFlash>,
+    mut rng: ResMut<RunRng>,
     audio: Res<Audio>,
     sound: Res<Sounds>,
     mut collision_events: EventReader<CollisionEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut hit_events: EventWriter<HitEvent>,
+    mut particle_events: EventWriter<ParticleEvent>,
+    mut shake_events: EventWriter<ShakeEvent>,
 ) {
     let mut handled_entities = HashSet::new();
+    let now = time.seconds_since_startup();
 
-    for ev in collision_events.iter() {
-        if let CollisionEvent::Started(e1, e2, _) = ev {
-            if handled_entities.contains(e1) || handled_entities.contains(e2) {
-                continue;
+    let mut contacts: Vec<(Entity, Entity)> = collision_events
+        .iter()
+        .filter_map(|ev| match ev {
+            CollisionEvent::Started(e1, e2, _) => Some((*e1, *e2)),
+            _ => None,
+        })
+        .collect();
+
+    // a sawblade sitting inside a zombie doesn't start a new collision, so once its rehit
+    // cooldown is over it has to find out what it's still touching
+    for (bullet, handle) in &rehitting {
+        for (h1, h2, intersecting) in rapier_context.narrow_phase.intersections_with(handle.0) {
+            let other = if h1 == handle.0 { h2 } else { h1 };
+            if let (true, Some(other)) = (intersecting, rapier_context.collider_entity(other)) {
+                contacts.push((bullet, other));
+            }
+        }
+    }
+
+    for (e1, e2) in &contacts {
+        if handled_entities.contains(e1) || handled_entities.contains(e2) {
+            continue;
+        }
+
+        let (&bullet_entity, maybe_enemy) = if bullets.get(*e1).is_ok() {
+            (e1, e2)
+        } else if bullets.get(*e2).is_ok() {
+            (e2, e1)
+        } else {
+            continue;
+        };
+
+        let (
+            _,
+            mut hit_enemies,
+            mut pierce,
+            damage,
+            crit,
+            knockback,
+            velocity,
+            &bullet_type,
+            rehit_cooldown,
+            owner,
+            frost,
+            leech,
+        ) = bullets.get_mut(bullet_entity).unwrap();
+        if let Ok((enemy_entity, enemy_transform, mut health, mut impulse, stunned)) =
+            enemies.get_mut(*maybe_enemy)
+            && can_hit(&hit_enemies, enemy_entity, rehit_cooldown, now)
+        {
+            match bullet_type {
+                BulletType::Regular => audio.play_with_settings(sound.bullet_hit.clone(), PlaybackSettings::ONCE.with_volume(0.1)),
+                BulletType::Rocket => audio.play_with_settings(sound.rocket_hit.clone(), PlaybackSettings::ONCE.with_volume(0.1)),
+                BulletType::SawBlade => audio.play_with_settings(sound.saw_hit.clone(), PlaybackSettings::ONCE.with_volume(0.1)),
+            };
+            hit_enemies.0.insert(enemy_entity, now);
+            pierce.0 -= 1;
+            impulse.impulse = knockback_impulse(velocity.linvel, knockback.0);
+            if pierce.0 <= 0 {
+                commands.entity(bullet_entity).despawn_recursive();
             }
 
-            let (&bullet_entity, maybe_enemy) = if let Ok(_) = bullets.get(*e1) {
-                (e1, e2)
-            } else if let Ok(_) = bullets.get(*e2) {
-                (e2, e1)
+            let is_crit = rng.gen_bool(crit.chance.clamp(0.0, 1.0) as f64);
+            let amount = if is_crit {
+                damage.0 * crit.multiplier
             } else {
-                continue;
+                damage.0
             };
 
-            let (_, mut hit_enemies, mut pierce, damage, knockback) =
-                bullets.get_mut(bullet_entity).unwrap();
-            if let Ok((enemy_entity, force, mut health, mut impulse)) =
-                enemies.get_mut(*maybe_enemy) && !hit_enemies.0.contains(&enemy_entity)
-            {
-                match *bullet_type {
-                    BulletType::Regular => audio.play_with_settings(sound.bullet_hit.clone(), PlaybackSettings::ONCE.with_volume(0.1)),
-                    BulletType::Rocket => audio.play_with_settings(sound.rocket_hit.clone(), PlaybackSettings::ONCE.with_volume(0.1)),
-                    BulletType::SawBlade => audio.play_with_settings(sound.saw_hit.clone(), PlaybackSettings::ONCE.with_volume(0.1)),
-                };
-                hit_enemies.0.insert(enemy_entity);
-                pierce.0 -= 1;
-                impulse.impulse = force.force.normalize() * -knockback.0;
-                if pierce.0 <= 0 {
-                    commands.entity(bullet_entity).despawn_recursive();
-                }
-                health.0 -= damage.0;
-                handled_entities.insert(bullet_entity);
-                handled_entities.insert(enemy_entity);
+            health.0 -= amount;
+
+            // a longer stun from an EMP isn't cut short
+            if let (Some(frost), None) = (frost, stunned) {
+                commands
+                    .entity(enemy_entity)
+                    .insert(Stunned(Timer::from_seconds(frost.0, false)));
             }
+
+            if let (Some(leech), Ok(mut tank)) = (leech, tanks.get_mut(owner.0)) {
+                tank.0 = (tank.0 + amount * leech.0).min(tank.1);
+            }
+
+            hit_events.send(HitEvent {
+                bullet_type,
+                damage: amount,
+            });
+            damage_events.send(DamageEvent {
+                position: enemy_transform.translation.truncate(),
+                amount,
+                kind: match (is_crit, bullet_type) {
+                    (true, _) => DamageKind::Crit,
+                    (false, BulletType::Rocket) => DamageKind::Explosion,
+                    (false, _) => DamageKind::Normal,
+                },
+            });
+
+            if let Ok(mut flash) = flashes.get_mut(enemy_entity) {
+                flash.restart();
+            } else {
+                commands.entity(enemy_entity).insert(HitFlash::default());
+            }
+
+            if let BulletType::Rocket = bullet_type {
+                shake_events.send(ShakeEvent(0.15));
+            }
+
+            particle_events.send(ParticleEvent {
+                position: enemy_transform.translation.truncate(),
+                kind: match bullet_type {
+                    BulletType::Rocket => ParticleKind::Sparks,
+                    _ => ParticleKind::Blood,
+                },
+                direction: Some(velocity.linvel),
+            });
+            handled_entities.insert(bullet_entity);
+            handled_entities.insert(enemy_entity);
         }
     }
 }
 
-fn handle_player_death(mut commands: Commands, player: Query<&Health, With<Player>>) {
-    let health = player.single();
+fn can_hit(
+    hit_enemies: &HitEnemies,
+    enemy: Entity,
+    rehit_cooldown: Option<&RehitCooldown>,
+    now: f64,
+) -> bool {
+    match (hit_enemies.0.get(&enemy), rehit_cooldown) {
+        (None, _) => true,
+        (Some(hit_at), Some(cooldown)) => now - hit_at >= cooldown.0,
+        (Some(_), None) => false,
+    }
+}
 
-    if health.0 <= 0.0 {
-        commands.insert_resource(NextState(GameState::GameOver));
+// pushes an enemy along the bullet's path, stunned enemies and spitters that stopped to aim
+// don't have a force of their own to push back against
+fn knockback_impulse(bullet_velocity: Vec2, knockback: f32) -> Vec2 {
+    bullet_velocity.normalize_or_zero() * knockback
+}
+
+// the arena doesn't have any obstacles yet, so its edges are the only thing to bounce off,
+// anything solid added later needs to bounce sawblades too
+fn ricochet_bullets(mut bullets: Query<(&Transform, &mut Velocity, &mut Ricochet), With<Bullet>>) {
+    let half_size = ARENA_SIZE / 2.0;
+
+    for (transform, mut velocity, mut ricochet) in &mut bullets {
+        if ricochet.0 == 0 {
+            continue;
+        }
+
+        let pos = transform.translation.truncate();
+        let mut bounced = false;
+
+        // only flip the velocity if it's still heading out, otherwise it'd get stuck on the edge
+        if pos.x.abs() > half_size.x && pos.x.signum() == velocity.linvel.x.signum() {
+            velocity.linvel.x = -velocity.linvel.x;
+            bounced = true;
+        }
+
+        if pos.y.abs() > half_size.y && pos.y.signum() == velocity.linvel.y.signum() {
+            velocity.linvel.y = -velocity.linvel.y;
+            bounced = true;
+        }
+
+        if bounced {
+            ricochet.0 -= 1;
+        }
     }
 }
 
-fn despawn_offscreen(
+fn return_boomerangs(
     mut commands: Commands,
-    windows: Res<Windows>,
-    bullets: Query<(Entity, &Transform), With<Bullet>>,
+    time: Res<Time>,
+    tanks: Query<&Transform, With<Player>>,
+    mut bullets: Query<
+        (Entity, &Transform, &Owner, &mut Velocity, &mut Boomerang),
+        (With<Bullet>, Without<Player>),
+    >,
 ) {
-    let window = windows.primary();
+    for (entity, transform, owner, mut velocity, mut boomerang) in &mut bullets {
+        boomerang.tick(time.delta());
+
+        if !boomerang.finished() {
+            continue;
+        }
 
+        // sawblades head back to the tank that threw them
+        let player_transform = if let Ok(transform) = tanks.get(owner.0) {
+            transform
+        } else {
+            continue;
+        };
+
+        let to_player = player_transform.translation.truncate() - transform.translation.truncate();
+
+        if to_player.length() < 50.0 {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        velocity.linvel = steer(velocity.linvel, to_player, 10.0 * time.delta_seconds());
+    }
+}
+
+fn home_bullets(
+    time: Res<Time>,
+    mut bullets: Query<
+        (
+            &mut Transform,
+            &mut Velocity,
+            &Homing,
+            &HitEnemies,
+            Option<&Boomerang>,
+            Option<&Spin>,
+        ),
+        With<Bullet>,
+    >,
+    enemies: Query<(Entity, &Transform), (With<Enemy>, Without<Bullet>)>,
+) {
+    for (mut transform, mut velocity, homing, hit_enemies, boomerang, spin) in &mut bullets {
+        // sawblades on their way back shouldn't get distracted
+        if boomerang.map_or(false, |boomerang| boomerang.finished()) {
+            continue;
+        }
+
+        let pos = transform.translation.truncate();
+
+        let target = enemies
+            .iter()
+            .filter(|(entity, _)| !hit_enemies.0.contains_key(entity))
+            .map(|(_, enemy_transform)| enemy_transform.translation.truncate() - pos)
+            .filter(|to_enemy| {
+                to_enemy.length() <= homing.range
+                    && velocity.linvel.angle_between(*to_enemy).abs() <= HOMING_CONE
+            })
+            .min_by(|a, b| a.length().total_cmp(&b.length()));
+
+        if let Some(to_enemy) = target {
+            velocity.linvel = steer(
+                velocity.linvel,
+                to_enemy,
+                homing.turn_rate * time.delta_seconds(),
+            );
+
+            if spin.is_none() {
+                transform.rotation = Quat::from_rotation_z(Vec2::X.angle_between(velocity.linvel));
+            }
+        }
+    }
+}
+
+fn spin_bullets(time: Res<Time>, mut bullets: Query<(&mut Transform, &Spin), With<Bullet>>) {
+    for (mut transform, spin) in &mut bullets {
+        transform.rotate(Quat::from_rotation_z(spin.0 * time.delta_seconds()));
+    }
+}
+
+/// Rotates `velocity` towards `target_dir` by at most `max_angle` radians, keeping its speed.
+fn steer(velocity: Vec2, target_dir: Vec2, max_angle: f32) -> Vec2 {
+    let angle = velocity.angle_between(target_dir);
+
+    if angle.is_nan() {
+        return velocity;
+    }
+
+    Vec2::from_angle(angle.clamp(-max_angle, max_angle)).rotate(velocity)
+}
+
+fn handle_player_death(
+    mut commands: Commands,
+    mut score: ResMut<Score>,
+    modifiers: Res<RunModifiers>,
+    mut tanks: Query<(Entity, &Health, &mut Sprite, Option<&Downed>), With<Player>>,
+) {
+    if tanks.is_empty() {
+        return;
+    }
+
+    let mut alive = 0;
+
+    for (entity, health, mut sprite, downed) in &mut tanks {
+        if downed.is_some() {
+            continue;
+        }
+
+        if health.0 <= 0.0 {
+            commands.entity(entity).insert(Downed);
+            sprite.color = Color::DARK_GRAY;
+        } else {
+            alive += 1;
+        }
+    }
+
+    // the run only ends once every tank is down
+    if alive == 0 {
+        score.0 = (score.0 as f32 * modifiers.effects().score).round() as u32;
+        commands.insert_resource(NextState(GameState::GameOver));
+    }
+}
+
+fn despawn_offscreen(mut commands: Commands, bullets: Query<(Entity, &Transform), With<Bullet>>) {
     for (entity, transform) in &bullets {
-        if transform.translation.x > window.width()
-            || transform.translation.x < -window.width()
-            || transform.translation.y > window.height()
-            || transform.translation.y < -window.height()
+        if transform.translation.x > ARENA_SIZE.x
+            || transform.translation.x < -ARENA_SIZE.x
+            || transform.translation.y > ARENA_SIZE.y
+            || transform.translation.y < -ARENA_SIZE.y
         {
             commands.entity(entity).despawn_recursive()
         }
@@ -337,3 +1062,19 @@ fn update_mouse_position(
         *mouse_pos = MousePosition(world_pos.truncate());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn knocking_back_a_stunned_zombie_stays_finite() {
+        // an emp zeroes the force of every zombie it stuns, the push only depends on the bullet
+        let impulse = knockback_impulse(Vec2::new(0.0, 1500.0), 2000.0);
+        assert!(impulse.is_finite());
+        assert_eq!(impulse, Vec2::new(0.0, 2000.0));
+
+        // a bullet that has stopped dead doesn't push at all
+        assert_eq!(knockback_impulse(Vec2::ZERO, 2000.0), Vec2::ZERO);
+    }
+}
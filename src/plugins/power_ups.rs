@@ -0,0 +1,259 @@
+use bevy::{prelude::*, utils::HashSet};
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::{Downed, Enemy, Health, Player},
+    despawn_with,
+    resources::{Sounds, Sprites},
+    DamageEvent, DamageKind, GameState, KillEvent, ParticleEvent, ParticleKind, RunRng, ShakeEvent,
+};
+
+// how long a dropped power-up waits to be picked up
+const PICKUP_LIFETIME: f32 = 10.0;
+// it blinks for this many seconds before it's gone
+const PICKUP_BLINK: f32 = 3.0;
+// a share of the tank's max health
+const HEAL_AMOUNT: f32 = 0.3;
+// the fire interval is divided by this while rapid fire lasts
+pub const RAPID_FIRE_SPEEDUP: f32 = 2.0;
+// extra shots on top of the fire pattern while triple shot lasts
+pub const TRIPLE_SHOT_EXTRA: u32 = 2;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerUp {
+    Heal,
+    RapidFire,
+    TripleShot,
+    Invulnerable,
+    Nuke,
+}
+
+impl PowerUp {
+    pub fn icon(&self, sprites: &Sprites) -> Handle<Image> {
+        sprites.power_ups[*self as usize].clone()
+    }
+
+    // in seconds, power-ups without one take effect right away
+    fn duration(&self) -> Option<f32> {
+        match self {
+            PowerUp::RapidFire => Some(8.0),
+            PowerUp::TripleShot => Some(8.0),
+            PowerUp::Invulnerable => Some(5.0),
+            PowerUp::Heal | PowerUp::Nuke => None,
+        }
+    }
+}
+
+// a power-up lying in the arena, gone when its timer runs out
+#[derive(Component, Deref, DerefMut)]
+pub struct Pickup(pub Timer);
+
+// the timed power-ups a tank has picked up, picking one up again restarts its timer
+#[derive(Component, Default)]
+pub struct Buffs(pub Vec<(PowerUp, Timer)>);
+
+impl Buffs {
+    pub fn has(&self, power_up: PowerUp) -> bool {
+        self.0.iter().any(|(active, _)| *active == power_up)
+    }
+
+    fn start(&mut self, power_up: PowerUp, duration: f32) {
+        let timer = Timer::from_seconds(duration, false);
+
+        match self.0.iter_mut().find(|(active, _)| *active == power_up) {
+            Some((_, old)) => *old = timer,
+            None => self.0.push((power_up, timer)),
+        }
+    }
+}
+
+pub struct PowerUpPlugin;
+
+impl Plugin for PowerUpPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            ConditionSet::new()
+                .run_in_state(GameState::Playing)
+                .with_system(drop_power_ups)
+                .with_system(collect_power_ups)
+                .with_system(expire_pickups)
+                .with_system(tick_buffs)
+                .with_system(tint_invulnerable)
+                .into(),
+        )
+        .add_enter_system(GameState::GameOver, despawn_with::<Pickup>)
+        .add_enter_system(GameState::Menu, despawn_with::<Pickup>);
+    }
+}
+
+fn drop_power_ups(
+    mut commands: Commands,
+    sprites: Res<Sprites>,
+    mut rng: ResMut<RunRng>,
+    mut kill_events: EventReader<KillEvent>,
+) {
+    for ev in kill_events.iter() {
+        // a single roll walks down the table, so the chances add up
+        let mut roll: f32 = rng.gen();
+        let power_up = ev.kind.drops().iter().find_map(|&(power_up, chance)| {
+            if roll < chance {
+                Some(power_up)
+            } else {
+                roll -= chance;
+                None
+            }
+        });
+
+        let power_up = if let Some(power_up) = power_up {
+            power_up
+        } else {
+            continue;
+        };
+
+        spawn_pickup(&mut commands, &sprites, power_up, ev.position);
+    }
+}
+
+// picked up like coins, through a sensor that only reports hitting the tank
+pub fn spawn_pickup(
+    commands: &mut Commands,
+    sprites: &Sprites,
+    power_up: PowerUp,
+    position: Vec2,
+) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: power_up.icon(sprites),
+            transform: Transform::from_translation(position.extend(0.5))
+                .with_scale(Vec3::splat(3.0)),
+            ..default()
+        })
+        .insert(power_up)
+        .insert(Pickup(Timer::from_seconds(PICKUP_LIFETIME, false)))
+        .insert(RigidBody::KinematicVelocityBased)
+        .insert(Collider::cuboid(6.0, 6.0))
+        .insert(Sensor)
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(ActiveCollisionTypes::KINEMATIC_STATIC)
+        .insert(Velocity::zero())
+        .id()
+}
+
+fn collect_power_ups(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    pickups: Query<(&Transform, &PowerUp), With<Pickup>>,
+    mut tanks: Query<(&mut Health, &mut Buffs), (With<Player>, Without<Downed>)>,
+    mut enemies: Query<(&Transform, &mut Health), (With<Enemy>, Without<Player>)>,
+    audio: Res<Audio>,
+    sound: Res<Sounds>,
+    mut particle_events: EventWriter<ParticleEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut shake_events: EventWriter<ShakeEvent>,
+) {
+    // both tanks can touch the same pickup in one frame
+    let mut collected = HashSet::new();
+
+    for ev in collision_events.iter() {
+        if let CollisionEvent::Started(e1, e2, _) = ev {
+            let (pickup, maybe_tank) = if pickups.contains(*e1) {
+                (*e1, *e2)
+            } else if pickups.contains(*e2) {
+                (*e2, *e1)
+            } else {
+                continue;
+            };
+
+            let (mut health, mut buffs) = if let Ok(tank) = tanks.get_mut(maybe_tank) {
+                tank
+            } else {
+                continue;
+            };
+
+            if !collected.insert(pickup) {
+                continue;
+            }
+
+            let (transform, power_up) = pickups.get(pickup).unwrap();
+
+            match power_up {
+                PowerUp::Heal => health.0 = (health.0 + health.1 * HEAL_AMOUNT).min(health.1),
+                PowerUp::Nuke => {
+                    for (enemy, mut health) in &mut enemies {
+                        if health.0 <= 0.0 {
+                            continue;
+                        }
+
+                        damage_events.send(DamageEvent {
+                            position: enemy.translation.truncate(),
+                            amount: health.0,
+                            kind: DamageKind::Explosion,
+                        });
+                        health.0 = 0.0;
+                    }
+                    shake_events.send(ShakeEvent(1.0));
+                }
+                _ => {
+                    if let Some(duration) = power_up.duration() {
+                        buffs.start(*power_up, duration);
+                    }
+                }
+            }
+
+            audio.play_with_settings(
+                sound.coin_pickup.clone(),
+                PlaybackSettings::ONCE.with_volume(0.2),
+            );
+            particle_events.send(ParticleEvent {
+                position: transform.translation.truncate(),
+                kind: ParticleKind::Sparkle,
+                direction: None,
+            });
+            commands.entity(pickup).despawn_recursive();
+        }
+    }
+}
+
+fn expire_pickups(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut pickups: Query<(Entity, &mut Pickup, &mut Visibility)>,
+) {
+    for (entity, mut pickup, mut visibility) in &mut pickups {
+        pickup.tick(time.delta());
+        if pickup.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let left = pickup.duration().as_secs_f32() - pickup.elapsed_secs();
+        visibility.is_visible = left > PICKUP_BLINK || (left * 8.0) as u32 % 2 == 0;
+    }
+}
+
+fn tick_buffs(time: Res<Time>, mut tanks: Query<&mut Buffs>) {
+    for mut buffs in &mut tanks {
+        for (_, timer) in &mut buffs.0 {
+            timer.tick(time.delta());
+        }
+        buffs.0.retain(|(_, timer)| !timer.finished());
+    }
+}
+
+// downed tanks are tinted elsewhere
+fn tint_invulnerable(mut tanks: Query<(&Buffs, &mut Sprite), (With<Player>, Without<Downed>)>) {
+    for (buffs, mut sprite) in &mut tanks {
+        let color = if buffs.has(PowerUp::Invulnerable) {
+            Color::GOLD
+        } else {
+            Color::WHITE
+        };
+
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use bevy::{prelude::*, utils::HashSet};
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+
+use crate::{
+    components::{Bullet, Damage, Downed, Enemy, Health, Player, Stunned},
+    despawn_with, nearest_tank,
+    resources::{Sounds, Sprites, ARENA_SIZE},
+    Buffs, DamageEvent, DamageKind, GameState, HitFlash, ParticleEvent, ParticleKind, PowerUp,
+    ShakeEvent,
+};
+
+// collision groups, only spit filters anything out so it flies straight through the horde
+pub const TANK_GROUP: u32 = 1 << 0;
+pub const BULLET_GROUP: u32 = 1 << 1;
+pub const ZOMBIE_GROUP: u32 = 1 << 2;
+pub const SPIT_GROUP: u32 = 1 << 3;
+
+// a tank has to be this close for a spitter to start winding up
+const SPIT_RANGE: f32 = 450.0;
+const SPIT_COOLDOWN: f32 = 3.0;
+const SPIT_WINDUP: f32 = 0.8;
+const SPIT_SPEED: f32 = 450.0;
+// of the spitter's bite damage
+const SPIT_DAMAGE: f32 = 1.5;
+
+// lets an enemy spit at tanks, the windup flashes it so there's time to dodge or shoot the spit
+#[derive(Component)]
+pub struct RangedAttack {
+    pub cooldown: Timer,
+    pub windup: Option<Timer>,
+}
+
+impl RangedAttack {
+    pub fn winding_up(&self) -> bool {
+        self.windup.is_some()
+    }
+}
+
+impl Default for RangedAttack {
+    fn default() -> RangedAttack {
+        RangedAttack {
+            cooldown: Timer::from_seconds(SPIT_COOLDOWN, false),
+            windup: None,
+        }
+    }
+}
+
+// hurts tanks it hits and can be shot down by their bullets
+#[derive(Component)]
+pub struct EnemyProjectile;
+
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            ConditionSet::new()
+                .run_in_state(GameState::Playing)
+                .with_system(spit)
+                .with_system(collide_projectiles)
+                .with_system(despawn_stray_projectiles)
+                .into(),
+        )
+        .add_enter_system(GameState::GameOver, despawn_with::<EnemyProjectile>)
+        .add_enter_system(GameState::Menu, despawn_with::<EnemyProjectile>);
+    }
+}
+
+fn spit(
+    mut commands: Commands,
+    time: Res<Time>,
+    sprites: Res<Sprites>,
+    tanks: Query<&Transform, (With<Player>, Without<Downed>)>,
+    mut spitters: Query<
+        (
+            &Transform,
+            &Damage,
+            &mut RangedAttack,
+            &mut Sprite,
+            Option<&mut HitFlash>,
+        ),
+        (With<Enemy>, Without<Stunned>, Without<Player>),
+    >,
+) {
+    for (transform, damage, mut attack, mut sprite, flash) in &mut spitters {
+        let position = transform.translation.truncate();
+        let target = if let Some(target) = nearest_tank(&tanks, position) {
+            target
+        } else {
+            return;
+        };
+
+        let windup = if let Some(windup) = &mut attack.windup {
+            windup
+        } else {
+            attack.cooldown.tick(time.delta());
+            if attack.cooldown.finished() && target.distance(position) <= SPIT_RANGE {
+                attack.windup = Some(Timer::from_seconds(SPIT_WINDUP, false));
+            }
+            continue;
+        };
+
+        // flashes faster and faster until it spits
+        windup.tick(time.delta());
+        let progress = windup.percent();
+        let finished = windup.finished();
+        let color = if finished || (progress * progress * 12.0) as u32 % 2 == 0 {
+            Color::WHITE
+        } else {
+            Color::rgb(0.5, 1.0, 0.3)
+        };
+        HitFlash::tint(&mut sprite, flash, color);
+
+        if !finished {
+            continue;
+        }
+
+        attack.windup = None;
+        attack.cooldown.reset();
+
+        // aimed at where the tank is now, not where it's going
+        let dir = (target - position).normalize_or_zero();
+
+        spawn_spit(
+            &mut commands,
+            &sprites,
+            position,
+            dir * SPIT_SPEED,
+            damage.0 * SPIT_DAMAGE,
+        );
+    }
+}
+
+pub fn spawn_spit(
+    commands: &mut Commands,
+    sprites: &Sprites,
+    position: Vec2,
+    velocity: Vec2,
+    damage: f32,
+) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: sprites.spit.clone(),
+            transform: Transform::from_translation(position.extend(2.0))
+                .with_scale(Vec3::splat(4.0)),
+            ..default()
+        })
+        .insert(EnemyProjectile)
+        .insert(Damage(damage))
+        .insert(RigidBody::KinematicVelocityBased)
+        .insert(Velocity::linear(velocity))
+        .insert(Collider::ball(3.0))
+        .insert(Sensor)
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC)
+        .insert(CollisionGroups::new(SPIT_GROUP, TANK_GROUP | BULLET_GROUP))
+        .id()
+}
+
+fn collide_projectiles(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    projectiles: Query<(&Transform, &Damage), With<EnemyProjectile>>,
+    mut tanks: Query<(&Transform, &mut Health, &Buffs), (With<Player>, Without<Downed>)>,
+    bullets: Query<(), With<Bullet>>,
+    audio: Res<Audio>,
+    sound: Res<Sounds>,
+    mut particle_events: EventWriter<ParticleEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut shake_events: EventWriter<ShakeEvent>,
+) {
+    // spit can touch a tank and a bullet in the same frame
+    let mut hit = HashSet::new();
+
+    for ev in collision_events.iter() {
+        if let CollisionEvent::Started(e1, e2, _) = ev {
+            let (projectile, other) = if projectiles.contains(*e1) {
+                (*e1, *e2)
+            } else if projectiles.contains(*e2) {
+                (*e2, *e1)
+            } else {
+                continue;
+            };
+
+            let (transform, damage) = projectiles.get(projectile).unwrap();
+
+            if let Ok((tank, mut health, buffs)) = tanks.get_mut(other) {
+                if !hit.insert(projectile) {
+                    continue;
+                }
+
+                if !buffs.has(PowerUp::Invulnerable) {
+                    audio.play_with_settings(
+                        sound.player_hit.clone(),
+                        PlaybackSettings::ONCE.with_volume(0.1),
+                    );
+                    health.0 -= damage.0;
+                    damage_events.send(DamageEvent {
+                        position: tank.translation.truncate(),
+                        amount: damage.0,
+                        kind: DamageKind::Player,
+                    });
+                    shake_events.send(ShakeEvent(0.3));
+                }
+            } else if bullets.contains(other) {
+                if !hit.insert(projectile) {
+                    continue;
+                }
+            } else {
+                continue;
+            }
+
+            particle_events.send(ParticleEvent {
+                position: transform.translation.truncate(),
+                kind: ParticleKind::Sparks,
+                direction: None,
+            });
+            commands.entity(projectile).despawn_recursive();
+        }
+    }
+}
+
+fn despawn_stray_projectiles(
+    mut commands: Commands,
+    projectiles: Query<(Entity, &Transform), With<EnemyProjectile>>,
+) {
+    for (entity, transform) in &projectiles {
+        let position = transform.translation.truncate();
+
+        // same margin as the tanks' bullets
+        if position.abs().cmpgt(ARENA_SIZE).any() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,815 @@
+use std::time::Duration;
+
+use bevy::{app::AppExit, ecs::system::SystemParam, prelude::*};
+use bevy_pkv::PkvStore;
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    button_pressed,
+    components::{
+        Aim, AttackTimer, Boomerang, Bullet, BulletType, Charge, Coin, Damage, Enemy, Health, Heat,
+        HitEnemies, Knockback, Loadout, Magnet, Overdrive, Owner, Pierce, Player, Ricochet,
+        ShootTimer, Stunned, Volley,
+    },
+    despawn_with,
+    resources::{Coins, Difficulty, EnemyScale, Score, SpawnTimer, Sprites, SurvivalTime},
+    spawn_bullet, spawn_enemy, spawn_pickup, spawn_spit, spawn_turret, turret_loadout,
+    AbilitySlots, Buffs, ContinueButton, EnemyKind, EnemyProjectile, GameState, Pickup, PowerUp,
+    PrevVelocity, RangedAttack, Respec, RunModifiers, RunProgress, RunRng, RunStats, Turret,
+    Upgrade, Upgrades,
+};
+
+// bump this whenever `SavedRun` changes, older saves are thrown away
+const RUN_VERSION: u32 = 10;
+
+// a timer's duration and how far along it is, in seconds
+type SavedTimer = [f32; 2];
+
+fn save_timer(timer: &Timer) -> SavedTimer {
+    [timer.duration().as_secs_f32(), timer.elapsed_secs()]
+}
+
+fn load_timer(saved: SavedTimer, repeating: bool) -> Timer {
+    let mut timer = Timer::from_seconds(saved[0], repeating);
+    timer.set_elapsed(Duration::from_secs_f32(saved[1]));
+    timer
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedRangedAttack {
+    cooldown: SavedTimer,
+    windup: Option<SavedTimer>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedEnemy {
+    kind: EnemyKind,
+    position: [f32; 2],
+    velocity: [f32; 2],
+    health: [f32; 2],
+    damage: f32,
+    attack: SavedTimer,
+    stunned: Option<SavedTimer>,
+    ranged: Option<SavedRangedAttack>,
+}
+
+// frost, leech and homing come from the tank's current upgrades
+#[derive(Serialize, Deserialize)]
+struct SavedBullet {
+    // index of the tank that fired it
+    owner: usize,
+    // the tank may have switched bullets since
+    bullet_type: BulletType,
+    position: [f32; 2],
+    rotation: [f32; 4],
+    // charged shots are bigger and hit harder than the loadout
+    scale: f32,
+    velocity: [f32; 2],
+    pierce: i32,
+    damage: f32,
+    knockback: f32,
+    ricochet: Option<u32>,
+    boomerang: Option<SavedTimer>,
+    // index of every enemy it has hit and how many seconds ago
+    hit_enemies: Vec<(usize, f64)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedPickup {
+    power_up: PowerUp,
+    position: [f32; 2],
+    timer: SavedTimer,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedSpit {
+    position: [f32; 2],
+    velocity: [f32; 2],
+    damage: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedVolley {
+    shots_left: u32,
+    timer: SavedTimer,
+    left_barrel: bool,
+    charge: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedTurret {
+    position: [f32; 2],
+    health: [f32; 2],
+}
+
+// a tank without health left comes back downed
+#[derive(Serialize, Deserialize)]
+struct SavedTank {
+    health: [f32; 2],
+    upgrades: Vec<Upgrade>,
+    // oldest first, drones aren't saved since they're rebuilt from the upgrades
+    turrets: Vec<SavedTurret>,
+    aim: [f32; 2],
+    shoot_timer: SavedTimer,
+    shoot_paused: bool,
+    volley: SavedVolley,
+    charge: f32,
+    // the heat and whether the barrel is locked, only in runs with the overheat mutator
+    heat: Option<(f32, bool)>,
+    // in the same order as the ability slots
+    cooldowns: Vec<SavedTimer>,
+    buffs: Vec<(PowerUp, SavedTimer)>,
+    overdrive: Option<SavedTimer>,
+    magnet: Option<SavedTimer>,
+    respecs_used: u32,
+}
+
+// everything needed to put a run back exactly where it was left, the loadouts aren't stored
+// since they're rebuilt from the upgrades. what's left out on purpose doesn't last long enough to
+// matter: coins on their way to a tank, the shoot timers of turrets and drones, barrel animations,
+// hit flashes, particles and corpses
+#[derive(Serialize, Deserialize)]
+struct SavedRun {
+    version: u32,
+    difficulty: Difficulty,
+    modifiers: RunModifiers,
+    coins: u32,
+    score: u32,
+    tanks: Vec<SavedTank>,
+    enemy_scale: f32,
+    spawn_interval: f32,
+    spawn_elapsed: f32,
+    survival_time: f32,
+    enemies: Vec<SavedEnemy>,
+    bullets: Vec<SavedBullet>,
+    pickups: Vec<SavedPickup>,
+    spit: Vec<SavedSpit>,
+    stats: RunStats,
+    progress: RunProgress,
+    // seeded and daily runs keep drawing the same numbers they would have without the break,
+    // a run never gets anywhere near 2^64 words into the stream
+    rng_seed: [u8; 32],
+    rng_word_pos: u64,
+}
+
+// a run loaded from the menu, restored once the player has been spawned
+pub struct ResumeRun(SavedRun);
+
+impl ResumeRun {
+    // the run comes back with as many tanks as it was saved with, whatever the co-op setting
+    pub fn tanks(&self) -> usize {
+        self.0.tanks.len()
+    }
+}
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(save_and_quit.run_in_state(GameState::Playing))
+            .add_system_to_stage(CoreStage::Last, save_on_exit)
+            .add_system(
+                load_run
+                    .run_in_state(GameState::Menu)
+                    .run_if(button_pressed::<ContinueButton>),
+            )
+            .add_system(
+                restore_run
+                    .run_in_state(GameState::Playing)
+                    .run_if_resource_exists::<ResumeRun>(),
+            )
+            // quitting to the menu leaves the world behind, unlike dying
+            .add_enter_system(GameState::Menu, despawn_with::<Enemy>)
+            .add_enter_system(GameState::Menu, despawn_with::<Bullet>)
+            .add_enter_system(GameState::Menu, despawn_with::<Player>)
+            .add_enter_system(GameState::Menu, despawn_with::<Coin>)
+            // a finished run can't be continued
+            .add_enter_system(GameState::GameOver, clear_saved_run);
+    }
+}
+
+pub fn has_saved_run(pkv: &PkvStore) -> bool {
+    matches!(pkv.get::<Option<SavedRun>>("run"), Ok(Some(saved)) if saved.version == RUN_VERSION)
+}
+
+fn clear_saved_run(mut pkv: ResMut<PkvStore>) {
+    pkv.set::<Option<SavedRun>>("run", &None)
+        .expect("failed to access pkv store");
+}
+
+// everything in the world that goes into a save
+#[derive(SystemParam)]
+struct RunState<'w, 's> {
+    difficulty: Res<'w, Difficulty>,
+    modifiers: Res<'w, RunModifiers>,
+    coins: Res<'w, Coins>,
+    score: Res<'w, Score>,
+    enemy_scale: Res<'w, EnemyScale>,
+    spawn_timer: Res<'w, SpawnTimer>,
+    survival_time: Res<'w, SurvivalTime>,
+    stats: Res<'w, RunStats>,
+    progress: Res<'w, RunProgress>,
+    rng: Res<'w, RunRng>,
+    tanks: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Player,
+            &'static Health,
+            &'static Upgrades,
+            &'static Aim,
+            &'static ShootTimer,
+            &'static Volley,
+            &'static Charge,
+            Option<&'static Heat>,
+            &'static AbilitySlots,
+            &'static Buffs,
+            Option<&'static Overdrive>,
+            Option<&'static Magnet>,
+            &'static Respec,
+        ),
+    >,
+    turrets: Query<
+        'w,
+        's,
+        (
+            &'static Transform,
+            &'static Health,
+            &'static Owner,
+            &'static Turret,
+        ),
+    >,
+    enemies: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static EnemyKind,
+            &'static Transform,
+            &'static Velocity,
+            &'static Health,
+            &'static Damage,
+            &'static AttackTimer,
+            Option<&'static Stunned>,
+            Option<&'static RangedAttack>,
+            Option<&'static PrevVelocity>,
+        ),
+        With<Enemy>,
+    >,
+    bullets: Query<
+        'w,
+        's,
+        (
+            &'static Transform,
+            &'static Velocity,
+            &'static Owner,
+            &'static BulletType,
+            &'static Pierce,
+            &'static Damage,
+            &'static Knockback,
+            &'static HitEnemies,
+            Option<&'static Ricochet>,
+            Option<&'static Boomerang>,
+            Option<&'static PrevVelocity>,
+        ),
+        With<Bullet>,
+    >,
+    pickups: Query<'w, 's, (&'static PowerUp, &'static Transform, &'static Pickup)>,
+    spit: Query<
+        'w,
+        's,
+        (
+            &'static Transform,
+            &'static Velocity,
+            &'static Damage,
+            Option<&'static PrevVelocity>,
+        ),
+        With<EnemyProjectile>,
+    >,
+}
+
+impl<'w, 's> RunState<'w, 's> {
+    // `now` is the time since startup, hits are saved relative to it
+    fn save(&self, pkv: &mut PkvStore, now: f64) {
+        let mut tanks: Vec<_> = self.tanks.iter().collect();
+        if tanks.is_empty() {
+            return;
+        }
+        tanks.sort_by_key(|(_, player, ..)| player.0);
+
+        // while the skill tree is open everything is frozen and the real velocity is stashed away
+        let velocity = |velocity: &Velocity, prev: Option<&PrevVelocity>| {
+            prev.map_or(velocity.linvel, |prev| prev.0.linvel)
+                .to_array()
+        };
+
+        // bullets point at the enemies they've hit by their index in here
+        let enemies: Vec<_> = self.enemies.iter().collect();
+
+        let saved = SavedRun {
+            version: RUN_VERSION,
+            difficulty: self.difficulty.clone(),
+            modifiers: self.modifiers.clone(),
+            coins: self.coins.0,
+            score: self.score.0,
+            tanks: tanks
+                .iter()
+                .map(
+                    |(
+                        tank,
+                        _,
+                        health,
+                        upgrades,
+                        aim,
+                        shoot_timer,
+                        volley,
+                        charge,
+                        heat,
+                        slots,
+                        buffs,
+                        overdrive,
+                        magnet,
+                        respec,
+                    )| {
+                        let mut turrets: Vec<_> = self
+                            .turrets
+                            .iter()
+                            .filter(|(_, _, owner, _)| owner.0 == *tank)
+                            .collect();
+                        turrets.sort_by(|(.., a), (.., b)| a.placed_at.total_cmp(&b.placed_at));
+
+                        SavedTank {
+                            health: [health.0, health.1],
+                            upgrades: upgrades.0.clone(),
+                            turrets: turrets
+                                .into_iter()
+                                .map(|(transform, health, ..)| SavedTurret {
+                                    position: transform.translation.truncate().to_array(),
+                                    health: [health.0, health.1],
+                                })
+                                .collect(),
+                            aim: aim.0.to_array(),
+                            shoot_timer: save_timer(shoot_timer),
+                            shoot_paused: shoot_timer.paused(),
+                            volley: SavedVolley {
+                                shots_left: volley.shots_left,
+                                timer: save_timer(&volley.timer),
+                                left_barrel: volley.left_barrel,
+                                charge: volley.charge,
+                            },
+                            charge: charge.0,
+                            heat: heat.map(|heat| (heat.value, heat.overheated)),
+                            cooldowns: slots
+                                .0
+                                .iter()
+                                .map(|slot| save_timer(&slot.cooldown))
+                                .collect(),
+                            buffs: buffs
+                                .0
+                                .iter()
+                                .map(|(power_up, timer)| (*power_up, save_timer(timer)))
+                                .collect(),
+                            overdrive: overdrive.map(|overdrive| save_timer(overdrive)),
+                            magnet: magnet.map(|magnet| save_timer(magnet)),
+                            respecs_used: respec.used,
+                        }
+                    },
+                )
+                .collect(),
+            enemy_scale: self.enemy_scale.0,
+            spawn_interval: self.spawn_timer.duration().as_secs_f32(),
+            spawn_elapsed: self.spawn_timer.elapsed_secs(),
+            survival_time: self.survival_time.elapsed_secs(),
+            enemies: enemies
+                .iter()
+                .map(
+                    |(_, kind, transform, vel, health, damage, attack, stunned, ranged, prev)| {
+                        SavedEnemy {
+                            kind: **kind,
+                            position: transform.translation.truncate().to_array(),
+                            velocity: velocity(vel, *prev),
+                            health: [health.0, health.1],
+                            damage: damage.0,
+                            attack: save_timer(attack),
+                            stunned: stunned.map(|stunned| save_timer(&stunned.0)),
+                            ranged: ranged.map(|ranged| SavedRangedAttack {
+                                cooldown: save_timer(&ranged.cooldown),
+                                windup: ranged.windup.as_ref().map(save_timer),
+                            }),
+                        }
+                    },
+                )
+                .collect(),
+            bullets: self
+                .bullets
+                .iter()
+                .filter_map(
+                    |(
+                        transform,
+                        vel,
+                        owner,
+                        bullet_type,
+                        pierce,
+                        damage,
+                        knockback,
+                        hit_enemies,
+                        ricochet,
+                        boomerang,
+                        prev,
+                    )| {
+                        // only bullets fired by tanks survive the save, turrets and drones aren't saved
+                        let (_, player, ..) =
+                            tanks.iter().find(|(entity, ..)| *entity == owner.0)?;
+                        Some(SavedBullet {
+                            owner: player.0,
+                            bullet_type: *bullet_type,
+                            position: transform.translation.truncate().to_array(),
+                            rotation: transform.rotation.to_array(),
+                            scale: transform.scale.x,
+                            velocity: velocity(vel, prev),
+                            pierce: pierce.0,
+                            damage: damage.0,
+                            knockback: knockback.0,
+                            ricochet: ricochet.map(|ricochet| ricochet.0),
+                            boomerang: boomerang.map(|boomerang| save_timer(boomerang)),
+                            hit_enemies: hit_enemies
+                                .0
+                                .iter()
+                                .filter_map(|(enemy, at)| {
+                                    let index = enemies.iter().position(|(e, ..)| e == enemy)?;
+                                    Some((index, now - at))
+                                })
+                                .collect(),
+                        })
+                    },
+                )
+                .collect(),
+            pickups: self
+                .pickups
+                .iter()
+                .map(|(power_up, transform, pickup)| SavedPickup {
+                    power_up: *power_up,
+                    position: transform.translation.truncate().to_array(),
+                    timer: save_timer(pickup),
+                })
+                .collect(),
+            spit: self
+                .spit
+                .iter()
+                .map(|(transform, vel, damage, prev)| SavedSpit {
+                    position: transform.translation.truncate().to_array(),
+                    velocity: velocity(vel, prev),
+                    damage: damage.0,
+                })
+                .collect(),
+            stats: self.stats.clone(),
+            progress: self.progress.clone(),
+            rng_seed: self.rng.get_seed(),
+            rng_word_pos: self.rng.get_word_pos() as u64,
+        };
+
+        pkv.set("run", &Some(saved))
+            .expect("failed to access pkv store");
+    }
+}
+
+fn save_and_quit(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    mut pkv: ResMut<PkvStore>,
+    run: RunState,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        run.save(&mut pkv, time.seconds_since_startup());
+        commands.insert_resource(NextState(GameState::Menu));
+    }
+}
+
+fn save_on_exit(
+    mut exit_events: EventReader<AppExit>,
+    state: Res<CurrentState<GameState>>,
+    time: Res<Time>,
+    mut pkv: ResMut<PkvStore>,
+    run: RunState,
+) {
+    if exit_events.iter().count() == 0 {
+        return;
+    }
+
+    if let GameState::Playing | GameState::SkillTree = state.0 {
+        run.save(&mut pkv, time.seconds_since_startup());
+    }
+}
+
+fn load_run(mut commands: Commands, mut pkv: ResMut<PkvStore>) {
+    if let Ok(Some(saved)) = pkv.get::<Option<SavedRun>>("run") {
+        if saved.version == RUN_VERSION {
+            // the modifiers have to be in place before the run starts
+            commands.insert_resource(saved.modifiers.clone());
+            commands.insert_resource(ResumeRun(saved));
+            commands.insert_resource(NextState(GameState::Playing));
+        }
+    }
+
+    // resuming uses the save up, so dying can't be undone by quitting
+    pkv.set::<Option<SavedRun>>("run", &None)
+        .expect("failed to access pkv store");
+}
+
+fn restore_run(
+    mut commands: Commands,
+    time: Res<Time>,
+    resume: Res<ResumeRun>,
+    sprites: Res<Sprites>,
+    mut coins: ResMut<Coins>,
+    mut score: ResMut<Score>,
+    mut enemy_scale: ResMut<EnemyScale>,
+    mut spawn_timer: ResMut<SpawnTimer>,
+    mut survival_time: ResMut<SurvivalTime>,
+    mut stats: ResMut<RunStats>,
+    mut progress: ResMut<RunProgress>,
+    mut rng: ResMut<RunRng>,
+    mut tanks: Query<(
+        Entity,
+        &Player,
+        &mut Health,
+        &mut Upgrades,
+        &mut Loadout,
+        &mut Aim,
+        &mut ShootTimer,
+        &mut Volley,
+        &mut Charge,
+        Option<&mut Heat>,
+        &mut AbilitySlots,
+        &mut Buffs,
+        &mut Respec,
+    )>,
+    mut pkv: ResMut<PkvStore>,
+) {
+    let saved = &resume.0;
+    let now = time.seconds_since_startup();
+
+    coins.0 = saved.coins;
+    score.0 = saved.score;
+    *stats = saved.stats.clone();
+    *progress = saved.progress.clone();
+
+    rng.0 = ChaCha12Rng::from_seed(saved.rng_seed);
+    rng.set_word_pos(saved.rng_word_pos as u128);
+
+    enemy_scale.0 = saved.enemy_scale;
+    spawn_timer.set_duration(Duration::from_secs_f32(saved.spawn_interval));
+    spawn_timer.set_elapsed(Duration::from_secs_f32(saved.spawn_elapsed));
+    survival_time.set_elapsed(Duration::from_secs_f32(saved.survival_time));
+
+    let mut owners = vec![];
+    for (
+        entity,
+        player,
+        mut health,
+        mut upgrades,
+        mut loadout,
+        mut aim,
+        mut shoot_timer,
+        mut volley,
+        mut charge,
+        heat,
+        mut slots,
+        mut buffs,
+        mut respec,
+    ) in &mut tanks
+    {
+        if let Some(tank) = saved.tanks.get(player.0) {
+            health.0 = tank.health[0];
+            health.1 = tank.health[1];
+            upgrades.0 = tank.upgrades.clone();
+            *loadout = upgrades.loadout(&saved.modifiers);
+
+            aim.0 = tank.aim.into();
+            shoot_timer.0 = load_timer(tank.shoot_timer, true);
+            if tank.shoot_paused {
+                shoot_timer.pause();
+            }
+            *volley = Volley {
+                shots_left: tank.volley.shots_left,
+                timer: load_timer(tank.volley.timer, true),
+                left_barrel: tank.volley.left_barrel,
+                charge: tank.volley.charge,
+            };
+            charge.0 = tank.charge;
+            respec.used = tank.respecs_used;
+            if let (Some(mut heat), Some((value, overheated))) = (heat, tank.heat) {
+                heat.value = value;
+                heat.overheated = overheated;
+            }
+
+            // the slots are rebuilt from the upgrades in the same order they were saved in
+            slots.sync(&upgrades);
+            for (slot, cooldown) in slots.0.iter_mut().zip(&tank.cooldowns) {
+                slot.cooldown = load_timer(*cooldown, false);
+            }
+            buffs.0 = tank
+                .buffs
+                .iter()
+                .map(|(power_up, timer)| (*power_up, load_timer(*timer, false)))
+                .collect();
+
+            if let Some(overdrive) = tank.overdrive {
+                commands
+                    .entity(entity)
+                    .insert(Overdrive(load_timer(overdrive, false)));
+            }
+            if let Some(magnet) = tank.magnet {
+                commands
+                    .entity(entity)
+                    .insert(Magnet(load_timer(magnet, false)));
+            }
+
+            // spaced out by a second each so the oldest one is still the first to go
+            for (index, turret) in tank.turrets.iter().enumerate() {
+                let entity = spawn_turret(
+                    &mut commands,
+                    &sprites,
+                    turret.position.into(),
+                    entity,
+                    turret_loadout(&upgrades, &saved.modifiers),
+                    now - (tank.turrets.len() - index) as f64,
+                );
+                commands
+                    .entity(entity)
+                    .insert(Health(turret.health[0], turret.health[1]));
+            }
+        }
+        owners.push((player.0, entity, loadout.clone()));
+    }
+
+    let mut enemies = vec![];
+    for enemy in &saved.enemies {
+        let entity = spawn_enemy(
+            &mut commands,
+            &sprites,
+            &saved.difficulty,
+            &saved.modifiers,
+            enemy.kind,
+            Vec2::from(enemy.position).extend(1.0),
+            saved.enemy_scale,
+        );
+
+        commands
+            .entity(entity)
+            .insert(Velocity::linear(enemy.velocity.into()))
+            .insert(Health(enemy.health[0], enemy.health[1]))
+            .insert(Damage(enemy.damage))
+            .insert(AttackTimer(load_timer(enemy.attack, true)));
+
+        if let Some(stunned) = enemy.stunned {
+            commands
+                .entity(entity)
+                .insert(Stunned(load_timer(stunned, false)));
+        }
+
+        if let Some(ranged) = &enemy.ranged {
+            commands.entity(entity).insert(RangedAttack {
+                cooldown: load_timer(ranged.cooldown, false),
+                windup: ranged.windup.map(|windup| load_timer(windup, false)),
+            });
+        }
+
+        enemies.push(entity);
+    }
+
+    for bullet in &saved.bullets {
+        let (owner, mut loadout) = if let Some((_, owner, loadout)) =
+            owners.iter().find(|(index, ..)| *index == bullet.owner)
+        {
+            (*owner, loadout.clone())
+        } else {
+            continue;
+        };
+        loadout.bullet_type = bullet.bullet_type;
+        loadout.pierce = Pierce(bullet.pierce);
+        loadout.damage = Damage(bullet.damage);
+        loadout.knockback = Knockback(bullet.knockback);
+
+        let position = Vec2::from(bullet.position).extend(0.0);
+        let velocity = Vec2::from(bullet.velocity);
+        let entity = spawn_bullet(
+            &mut commands,
+            &sprites,
+            &loadout,
+            owner,
+            position,
+            velocity.normalize_or_zero(),
+        );
+
+        let mut entity = commands.entity(entity);
+        entity
+            .insert(Velocity::linear(velocity))
+            .insert(
+                Transform::from_translation(position)
+                    .with_rotation(Quat::from_array(bullet.rotation))
+                    .with_scale(Vec3::splat(bullet.scale)),
+            )
+            .insert(HitEnemies(
+                bullet
+                    .hit_enemies
+                    .iter()
+                    .filter_map(|(index, ago)| Some((*enemies.get(*index)?, now - ago)))
+                    .collect(),
+            ));
+
+        match bullet.ricochet {
+            Some(bounces) => entity.insert(Ricochet(bounces)),
+            None => entity.remove::<Ricochet>(),
+        };
+        match bullet.boomerang {
+            Some(timer) => entity.insert(Boomerang(load_timer(timer, false))),
+            None => entity.remove::<Boomerang>(),
+        };
+    }
+
+    for pickup in &saved.pickups {
+        let entity = spawn_pickup(
+            &mut commands,
+            &sprites,
+            pickup.power_up,
+            pickup.position.into(),
+        );
+        commands
+            .entity(entity)
+            .insert(Pickup(load_timer(pickup.timer, false)));
+    }
+
+    for spit in &saved.spit {
+        spawn_spit(
+            &mut commands,
+            &sprites,
+            spit.position.into(),
+            spit.velocity.into(),
+            spit.damage,
+        );
+    }
+
+    // the run keeps going on the difficulty it was started on, which becomes the selected one
+    // unless it's a daily, those are always on normal
+    if !saved.modifiers.daily {
+        pkv.set("difficulty", &saved.difficulty)
+            .expect("failed to access pkv store");
+    }
+    commands.insert_resource(saved.difficulty.clone());
+    commands.remove_resource::<ResumeRun>();
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn timers_survive_a_round_trip() {
+        let mut timer = Timer::from_seconds(2.5, true);
+        timer.tick(Duration::from_secs_f32(1.25));
+
+        let json = serde_json::to_string(&save_timer(&timer)).unwrap();
+        let loaded = load_timer(serde_json::from_str(&json).unwrap(), true);
+
+        assert_eq!(loaded.duration(), timer.duration());
+        assert!((loaded.elapsed_secs() - 1.25).abs() < 1e-6);
+        assert!(loaded.repeating());
+        assert!(!loaded.finished());
+    }
+
+    #[test]
+    fn finished_timers_finish_again_on_the_next_tick() {
+        let mut timer = Timer::from_seconds(1.0, false);
+        timer.tick(Duration::from_secs(2));
+
+        let mut loaded = load_timer(save_timer(&timer), false);
+        loaded.tick(Duration::ZERO);
+
+        assert!(loaded.finished());
+    }
+
+    #[test]
+    fn the_rng_picks_up_where_it_left_off() {
+        let mut rng = ChaCha12Rng::seed_from_u64(114);
+        for _ in 0..37 {
+            rng.gen::<f32>();
+        }
+
+        // through a u64 like the save does
+        let mut loaded = ChaCha12Rng::from_seed(rng.get_seed());
+        loaded.set_word_pos(rng.get_word_pos() as u64 as u128);
+
+        for _ in 0..10 {
+            assert_eq!(loaded.gen::<u64>(), rng.gen::<u64>());
+        }
+    }
+}
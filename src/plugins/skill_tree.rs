@@ -1,44 +1,375 @@
-use std::time::Duration;
-
-use bevy::{prelude::*, ui::FocusPolicy, utils::HashSet};
+use bevy::{prelude::*, ui::FocusPolicy};
 use bevy_rapier2d::prelude::*;
 use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    components::{Damage, Knockback, Pierce, Player},
+    components::{BulletType, Controls, Downed, Loadout, Player},
     despawn_with,
-    resources::{BulletType, Coins, Fonts, ShootTimer, Spread, Sprites},
-    GameState,
+    resources::{Coins, Difficulty, Fonts, MetaProgress, Sprites},
+    GameState, RunModifiers, TankInput,
 };
 
 #[derive(Component)]
-struct PrevVelocity(Velocity);
+pub struct PrevVelocity(pub Velocity);
 
 #[derive(Component)]
 struct PrevForce(ExternalForce);
 
+// spit and pickups are kinematic, everything else is dynamic
+#[derive(Component)]
+struct PrevBody(RigidBody);
+
 #[derive(Component)]
-struct Lock(bool, u32);
+struct Lock(bool);
 
 #[derive(Component)]
 struct TreeBranch;
 
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Upgrade {
+    Rocket,
+    SawBlade,
+    Spread1,
+    Spread2,
+    Homing1,
+    Homing2,
+    Fan,
+    Burst,
+    TwinBarrels,
+    RearGun,
+    Crit1,
+    Crit2,
+    Turret,
+    TurretNest,
+    Drone,
+    DroneSwarm,
+    Shockwave,
+    Emp,
+    Magnet,
+    Overdrive,
+    HeatSink,
+    Coolant,
+    ChargedShot,
+    QuickCharge,
+    Boomerang,
+    Ricochet,
+    Frost,
+    Leech,
+}
+
+// every column of the skill tree, each node requires the one above it unless it stands alone
+const TREE: [[Upgrade; 2]; 14] = [
+    [Upgrade::Rocket, Upgrade::SawBlade],
+    [Upgrade::Boomerang, Upgrade::Ricochet],
+    [Upgrade::Spread1, Upgrade::Spread2],
+    [Upgrade::Homing1, Upgrade::Homing2],
+    [Upgrade::Fan, Upgrade::Burst],
+    [Upgrade::TwinBarrels, Upgrade::RearGun],
+    [Upgrade::Crit1, Upgrade::Crit2],
+    [Upgrade::Frost, Upgrade::Leech],
+    [Upgrade::Turret, Upgrade::TurretNest],
+    [Upgrade::Drone, Upgrade::DroneSwarm],
+    [Upgrade::Shockwave, Upgrade::Emp],
+    [Upgrade::Magnet, Upgrade::Overdrive],
+    [Upgrade::HeatSink, Upgrade::Coolant],
+    [Upgrade::ChargedShot, Upgrade::QuickCharge],
+];
+
+// the tree is 1200px wide and every branch takes up 150px of that
+const BRANCHES_PER_ROW: usize = 8;
+
+impl Upgrade {
+    fn branch(&self) -> (usize, usize) {
+        for (column, branch) in TREE.iter().enumerate() {
+            if let Some(row) = branch.iter().position(|upgrade| upgrade == self) {
+                return (column, row);
+            }
+        }
+
+        unreachable!()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Upgrade::Rocket => "Rockets",
+            Upgrade::SawBlade => "Sawblades",
+            Upgrade::Spread1 => "Steady Aim",
+            Upgrade::Spread2 => "Dead Eye",
+            Upgrade::Homing1 => "Homing",
+            Upgrade::Homing2 => "Heat Seekers",
+            Upgrade::Fan => "Fan Shot",
+            Upgrade::Burst => "Burst Fire",
+            Upgrade::TwinBarrels => "Twin Barrels",
+            Upgrade::RearGun => "Rear Gun",
+            Upgrade::Crit1 => "Weak Spots",
+            Upgrade::Crit2 => "Executioner",
+            Upgrade::Turret => "Auto-Turret",
+            Upgrade::TurretNest => "Turret Nest",
+            Upgrade::Drone => "Drone",
+            Upgrade::DroneSwarm => "Drone Swarm",
+            Upgrade::Shockwave => "Shockwave",
+            Upgrade::Emp => "EMP Blast",
+            Upgrade::Magnet => "Coin Magnet",
+            Upgrade::Overdrive => "Overdrive",
+            Upgrade::HeatSink => "Heat Sink",
+            Upgrade::Coolant => "Coolant",
+            Upgrade::ChargedShot => "Charged Shot",
+            Upgrade::QuickCharge => "Quick Charge",
+            Upgrade::Boomerang => "Boomerang",
+            Upgrade::Ricochet => "Ricochet",
+            Upgrade::Frost => "Frost Rounds",
+            Upgrade::Leech => "Leech Rounds",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Upgrade::Rocket => "Fire rockets that hit hard and knock zombies back.",
+            Upgrade::SawBlade => "Fire sawblades that pierce and bounce off walls.",
+            Upgrade::Spread1 => "Tighter spread and a slightly faster trigger.",
+            Upgrade::Spread2 => "Every shot goes exactly where you aim.",
+            Upgrade::Homing1 => "Bullets curve towards nearby zombies.",
+            Upgrade::Homing2 => "Bullets turn harder and lock on from further away.",
+            Upgrade::Fan => "Fire three bullets in a fan, but a bit slower.",
+            Upgrade::Burst => "Every trigger fires a burst of three volleys.",
+            Upgrade::TwinBarrels => "Volleys alternate between two barrels.",
+            Upgrade::RearGun => "An extra gun fires straight behind you.",
+            Upgrade::Crit1 => "Bullets are more likely to crit.",
+            Upgrade::Crit2 => "Crits deal triple damage.",
+            Upgrade::Turret => {
+                "Shift + click (West on a gamepad) places a turret that shoots nearby zombies."
+            }
+            Upgrade::TurretNest => "Keep two turrets out at once, and they fire faster.",
+            Upgrade::Drone => "A drone circles your tank and shoots nearby zombies.",
+            Upgrade::DroneSwarm => "A second drone joins in and both hit harder.",
+            Upgrade::Shockwave => "Ability: knock every nearby zombie away from you.",
+            Upgrade::Emp => "Ability: stun every zombie for a few seconds.",
+            Upgrade::Magnet => "Ability: pull in every coin for a few seconds.",
+            Upgrade::Overdrive => "Ability: fire twice as fast for a few seconds.",
+            Upgrade::HeatSink => "The barrel holds half again as much heat before it overheats.",
+            Upgrade::Coolant => "The barrel cools down half again as fast.",
+            Upgrade::ChargedShot => {
+                "Hold fire to charge a bigger, harder hitting volley and release to fire it."
+            }
+            Upgrade::QuickCharge => "Shots charge up in a little over half the time.",
+            Upgrade::Boomerang => "Sawblades fly back to you after a moment.",
+            Upgrade::Ricochet => "Sawblades bounce off walls three more times.",
+            Upgrade::Frost => "Zombies you hit are frozen in place for a moment.",
+            Upgrade::Leech => "Heal for a tenth of the damage your shots deal.",
+        }
+    }
+
+    pub fn cost(&self) -> u32 {
+        match self.branch() {
+            (_, 0) => 20,
+            _ => 120,
+        }
+    }
+
+    pub fn requires(&self) -> Option<Upgrade> {
+        match self.branch() {
+            (_, 0) => None,
+            // abilities and the workshop unlocks only share a column to save space, each one can
+            // be bought on its own
+            _ if matches!(self, Upgrade::Emp | Upgrade::Overdrive | Upgrade::Leech) => None,
+            (column, row) => Some(TREE[column][row - 1]),
+        }
+    }
+
+    // some nodes have to be unlocked in the workshop before they show up in runs
+    pub fn unlocked(&self, meta: &MetaProgress) -> bool {
+        match self {
+            Upgrade::Frost => meta.frost,
+            Upgrade::Leech => meta.leech,
+            _ => true,
+        }
+    }
+
+    pub fn icon(&self, sprites: &Sprites) -> Handle<Image> {
+        let (column, row) = self.branch();
+
+        [
+            &sprites.bullet_type,
+            &sprites.saw_tricks,
+            &sprites.spread,
+            &sprites.homing,
+            &sprites.multishot,
+            &sprites.guns,
+            &sprites.crit,
+            &sprites.effects,
+            &sprites.turrets,
+            &sprites.drones,
+            &sprites.blasts,
+            &sprites.boosts,
+            &sprites.cooling,
+            &sprites.charge,
+        ][column][row]
+            .clone()
+    }
+
+    pub fn apply(&self, loadout: &mut Loadout) {
+        match self {
+            Upgrade::Rocket => {
+                loadout.bullet_type = BulletType::Rocket;
+                loadout.damage.0 = 100.0;
+                loadout.knockback.0 = 2000.0;
+                loadout.fire_interval -= 0.0625;
+            }
+            Upgrade::SawBlade => {
+                loadout.bullet_type = BulletType::SawBlade;
+                loadout.damage.0 = 140.0;
+                loadout.pierce.0 = 4;
+                loadout.knockback.0 = 0.0;
+                loadout.fire_interval -= 0.03125;
+            }
+            Upgrade::Spread1 => {
+                loadout.spread.next();
+                loadout.fire_interval -= 0.01;
+            }
+            Upgrade::Spread2 => {
+                loadout.spread.next();
+                loadout.fire_interval -= 0.015;
+            }
+            Upgrade::Homing1 | Upgrade::Homing2 => loadout.homing.next(),
+            Upgrade::Fan => {
+                loadout.pattern.shots = 3;
+                loadout.fire_interval += 0.05;
+            }
+            Upgrade::Burst => {
+                loadout.pattern.burst = 3;
+                loadout.fire_interval += 0.1;
+            }
+            Upgrade::TwinBarrels => loadout.pattern.twin = true,
+            Upgrade::RearGun => loadout.pattern.rear = true,
+            Upgrade::Crit1 => loadout.crit.chance += 0.15,
+            Upgrade::Crit2 => loadout.crit.multiplier = 3.0,
+            Upgrade::HeatSink => loadout.heat_capacity *= 1.5,
+            Upgrade::Coolant => loadout.cooling *= 1.5,
+            Upgrade::ChargedShot => loadout.charge_time = Some(1.5),
+            Upgrade::QuickCharge => loadout.charge_time = Some(0.9),
+            Upgrade::Boomerang => loadout.saw_blade.boomerang = true,
+            Upgrade::Ricochet => loadout.saw_blade.bounces += 3,
+            Upgrade::Frost => loadout.frost = 0.5,
+            Upgrade::Leech => loadout.leech = 0.1,
+            // companions have loadouts of their own and abilities go into slots instead
+            Upgrade::Turret
+            | Upgrade::TurretNest
+            | Upgrade::Drone
+            | Upgrade::DroneSwarm
+            | Upgrade::Shockwave
+            | Upgrade::Emp
+            | Upgrade::Magnet
+            | Upgrade::Overdrive => {}
+        }
+    }
+}
+
+// in the order they were bought
 #[derive(Component, Default)]
-pub struct Upgrades(HashSet<Handle<Image>>);
+pub struct Upgrades(pub Vec<Upgrade>);
+
+impl Upgrades {
+    pub fn spent(&self) -> u32 {
+        self.0.iter().map(Upgrade::cost).sum()
+    }
+
+    // replays every upgrade on top of the starting loadout, so dropping one reverts its effects
+    pub fn loadout(&self, modifiers: &RunModifiers) -> Loadout {
+        let mut loadout = modifiers.base_loadout();
+        for upgrade in &self.0 {
+            upgrade.apply(&mut loadout);
+        }
+
+        // some upgrades set the damage outright, so modifiers scale whatever they ended up with
+        loadout.damage.0 *= modifiers.effects().damage;
+
+        loadout
+    }
+}
+
+// respecs a tank has used this run, the difficulty decides how many every tank gets and what
+// they give back
+#[derive(Component, Default)]
+pub struct Respec {
+    pub used: u32,
+}
+
+impl Respec {
+    pub fn left(&self, difficulty: &Difficulty) -> u32 {
+        difficulty.respecs.saturating_sub(self.used)
+    }
+}
+
+#[derive(Component)]
+struct RespecButton;
+
+#[derive(Component)]
+struct RespecText;
 
 #[derive(Component)]
 struct SkillTreeMenu;
 
-struct TreeEvent(Entity);
+#[derive(Component)]
+struct Tooltip;
+
+// the tank whose skill tree is open
+pub struct TreeOwner(pub Entity);
+
+// the node a gamepad has selected, as a column and a row of `TREE`
+#[derive(Default)]
+struct TreeCursor {
+    column: usize,
+    row: usize,
+}
+
+impl TreeCursor {
+    fn upgrade(&self) -> Upgrade {
+        TREE[self.column][self.row]
+    }
+
+    // branches grow upwards and wrap onto a second row below the first one
+    fn step(&mut self, step: IVec2) {
+        let columns = TREE.len() as i32;
+        self.column = (self.column as i32 + step.x).rem_euclid(columns) as usize;
+
+        match (step.y, self.row) {
+            (1, 0) => self.row = 1,
+            (-1, 1) => self.row = 0,
+            (1, _) if self.column >= BRANCHES_PER_ROW => {
+                self.column -= BRANCHES_PER_ROW;
+                self.row = 0;
+            }
+            (-1, _) if self.column + BRANCHES_PER_ROW < TREE.len() => {
+                self.column += BRANCHES_PER_ROW;
+                self.row = 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+// sent whenever an upgrade is bought
+pub struct TreeEvent(pub Upgrade);
+
+// sent whenever a tank respecs, with the coins it got back
+pub struct RespecEvent(pub u32);
 
 pub struct SkillTreePlugin;
 
 impl Plugin for SkillTreePlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<TreeEvent>()
-            .init_resource::<Upgrades>()
+            .add_event::<RespecEvent>()
+            .init_resource::<TreeCursor>()
             .add_system(open_skill_tree.run_in_state(GameState::Playing))
             .add_enter_system(GameState::SkillTree, spawn_skill_tree)
+            // a respec rebuilds the tree with every lock back in place, without leaving the state
+            .add_system(
+                spawn_skill_tree
+                    .run_in_state(GameState::SkillTree)
+                    .run_on_event::<RespecEvent>(),
+            )
             .add_enter_system(GameState::SkillTree, pause)
             .add_exit_system(GameState::SkillTree, unpause)
             .add_exit_system(GameState::SkillTree, despawn_with::<SkillTreeMenu>)
@@ -46,42 +377,82 @@ impl Plugin for SkillTreePlugin {
                 ConditionSet::new()
                     .run_in_state(GameState::SkillTree)
                     .with_system(close_skill_tree)
+                    .with_system(navigate_tree)
                     .with_system(handle_button_press)
                     .with_system(update_locks)
+                    .with_system(show_tooltip)
+                    .with_system(move_tooltip)
+                    .with_system(update_respec_text)
                     .into(),
+            )
+            .add_system(
+                respec
+                    .run_in_state(GameState::SkillTree)
+                    .run_if(respec_pressed),
             );
     }
 }
 
-fn open_skill_tree(mut commands: Commands, mouse: Res<Input<MouseButton>>) {
-    if mouse.any_just_pressed([MouseButton::Right, MouseButton::Middle]) {
-        commands.insert_resource(NextState(GameState::SkillTree));
+fn open_skill_tree(
+    mut commands: Commands,
+    input: TankInput,
+    modifiers: Res<RunModifiers>,
+    tanks: Query<(Entity, &Controls), (With<Player>, Without<Downed>)>,
+) {
+    if !modifiers.allows_skill_tree() {
+        return;
+    }
+
+    for (tank, controls) in &tanks {
+        if input.toggle_tree(*controls) {
+            commands.insert_resource(TreeOwner(tank));
+            commands.insert_resource(NextState(GameState::SkillTree));
+            return;
+        }
     }
 }
 
-fn close_skill_tree(mut commands: Commands, mouse: Res<Input<MouseButton>>) {
-    if mouse.any_just_pressed([MouseButton::Right, MouseButton::Middle]) {
+// either player can close the tree, the mouse is needed to buy things anyway
+fn close_skill_tree(
+    mut commands: Commands,
+    input: TankInput,
+    tanks: Query<&Controls, With<Player>>,
+) {
+    if tanks.iter().any(|controls| input.toggle_tree(*controls)) {
         commands.insert_resource(NextState(GameState::Playing));
     }
 }
 
 fn spawn_skill_tree(
     mut commands: Commands,
-    upgrades: Res<Upgrades>,
+    owner: Res<TreeOwner>,
+    tanks: Query<(&Player, &Upgrades, &Controls)>,
+    meta: Res<MetaProgress>,
+    modifiers: Res<RunModifiers>,
     fonts: Res<Fonts>,
     sprites: Res<Sprites>,
 ) {
+    let (player, upgrades, controls) = if let Ok(tank) = tanks.get(owner.0) {
+        tank
+    } else {
+        return;
+    };
+
     commands
         .spawn_bundle(NodeBundle {
             color: UiColor([0.0; 4].into()),
             style: Style {
                 margin: UiRect::all(Val::Auto),
+                size: Size::new(Val::Px(1200.0), Val::Auto),
+                // too many branches for one row, the reverse keeps the first row on top
+                flex_wrap: FlexWrap::WrapReverse,
+                justify_content: JustifyContent::Center,
                 ..default()
             },
             ..default()
         })
         .with_children(|parent| {
-            for images in [sprites.bullet_type.clone(), sprites.spread.clone()] {
+            for branch in TREE {
                 parent
                     .spawn_bundle(NodeBundle {
                         color: UiColor([0.0; 4].into()),
@@ -94,27 +465,30 @@ fn spawn_skill_tree(
                     })
                     .insert(TreeBranch)
                     .with_children(|parent| {
-                        for (idx, image) in images.iter().enumerate() {
+                        for upgrade in branch {
                             parent
                                 .spawn_bundle(ButtonBundle {
                                     style: Style {
                                         size: Size::new(Val::Px(100.0), Val::Px(100.0)),
-                                        margin: UiRect::all(Val::Px(60.0)),
+                                        margin: UiRect::all(Val::Px(25.0)),
                                         ..default()
                                     },
                                     color: UiColor([0.8; 4].into()),
                                     ..default()
                                 })
+                                .insert(upgrade)
                                 .with_children(|parent| {
                                     parent.spawn_bundle(ImageBundle {
-                                        image: UiImage(image.clone()),
+                                        image: UiImage(upgrade.icon(&sprites)),
                                         focus_policy: FocusPolicy::Pass,
                                         ..default()
                                     });
-                                    if !upgrades.0.contains(&image.clone()) {
-                                        let unlocked = idx == 0
-                                            || (idx == 1
-                                                && upgrades.0.contains(&images[0].clone()));
+                                    if !upgrades.0.contains(&upgrade) {
+                                        let unlocked = upgrade.unlocked(&meta)
+                                            && modifiers.allows(upgrade)
+                                            && upgrade.requires().map_or(true, |required| {
+                                                upgrades.0.contains(&required)
+                                            });
                                         parent
                                             .spawn_bundle(ImageBundle {
                                                 style: Style {
@@ -129,10 +503,7 @@ fn spawn_skill_tree(
                                                 focus_policy: FocusPolicy::Pass,
                                                 ..default()
                                             })
-                                            .insert(Lock(
-                                                unlocked,
-                                                if idx == 0 { 20 } else { 120 },
-                                            ));
+                                            .insert(Lock(unlocked));
                                     }
                                     parent.spawn_bundle(TextBundle {
                                         style: Style {
@@ -144,13 +515,14 @@ fn spawn_skill_tree(
                                             ..default()
                                         },
                                         text: Text::from_section(
-                                            format!("{}", if idx == 0 { 20 } else { 120 }),
+                                            format!("{}", upgrade.cost()),
                                             TextStyle {
                                                 color: Color::YELLOW,
                                                 font: fonts.main.clone(),
                                                 font_size: 30.0,
                                             },
                                         ),
+                                        focus_policy: FocusPolicy::Pass,
                                         ..default()
                                     });
                                 });
@@ -159,71 +531,156 @@ fn spawn_skill_tree(
             }
         })
         .insert(SkillTreeMenu);
+
+    commands
+        .spawn_bundle(NodeBundle {
+            color: UiColor([0.0; 4].into()),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(20.0),
+                    ..default()
+                },
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(SkillTreeMenu)
+        .with_children(|parent| {
+            // with two tanks it's easy to lose track of whose upgrades these are
+            if tanks.iter().count() > 1 {
+                parent.spawn_bundle(TextBundle::from_section(
+                    format!("Player {}", player.0 + 1),
+                    TextStyle {
+                        color: Color::YELLOW,
+                        font: fonts.main.clone(),
+                        font_size: 30.0,
+                    },
+                ));
+            }
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    color: UiColor(Color::BLACK),
+                    style: Style {
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(10.0)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(RespecButton)
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                color: Color::WHITE,
+                                font: fonts.main.clone(),
+                                font_size: 30.0,
+                            },
+                        ))
+                        .insert(RespecText);
+                });
+
+            if let Controls::Gamepad(_) = controls {
+                parent.spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: UiRect::all(Val::Px(10.0)),
+                        ..default()
+                    },
+                    text: Text::from_section(
+                        "D-pad: pick, South: buy, West: respec, North: close",
+                        TextStyle {
+                            color: Color::GRAY,
+                            font: fonts.main.clone(),
+                            font_size: 20.0,
+                        },
+                    ),
+                    ..default()
+                });
+            }
+        });
+}
+
+// moves the gamepad's selection and highlights it, the mouse has hovering for that
+fn navigate_tree(
+    owner: Res<TreeOwner>,
+    tanks: Query<&Controls>,
+    input: TankInput,
+    mut cursor: ResMut<TreeCursor>,
+    mut buttons: Query<(&Upgrade, &mut UiColor), With<Button>>,
+) {
+    let controls = if let Ok(controls) = tanks.get(owner.0) {
+        *controls
+    } else {
+        return;
+    };
+
+    if let Controls::Mouse = controls {
+        return;
+    }
+
+    let step = input.tree_step(controls);
+    if step != IVec2::ZERO {
+        cursor.step(step);
+    }
+
+    for (upgrade, mut color) in &mut buttons {
+        let selected = *upgrade == cursor.upgrade();
+        let wanted = UiColor([if selected { 1.0 } else { 0.6 }; 4].into());
+        if color.0 != wanted.0 {
+            *color = wanted;
+        }
+    }
 }
 
 fn handle_button_press(
     mut commands: Commands,
-    sprites: Res<Sprites>,
-    mut upgrades: ResMut<Upgrades>,
+    owner: Res<TreeOwner>,
+    mut tanks: Query<(&mut Upgrades, &mut Loadout, &Controls)>,
     mut coins: ResMut<Coins>,
-    mut pierce: ResMut<Pierce>,
-    mut damage: ResMut<Damage>,
-    mut knockback: ResMut<Knockback>,
-    mut spread: ResMut<Spread>,
-    mut bullet_type: ResMut<BulletType>,
+    modifiers: Res<RunModifiers>,
     mut tree_events: EventWriter<TreeEvent>,
-    mut shoot_timer: ResMut<ShootTimer>,
-    buttons: Query<(Entity, &Interaction, &Children), (Changed<Interaction>, With<Button>)>,
-    icons: Query<&UiImage, Without<Lock>>,
-    locks: Query<(Entity, &Lock)>,
+    input: TankInput,
+    cursor: Res<TreeCursor>,
+    buttons: Query<(&Interaction, &Upgrade, &Children), (Changed<Interaction>, With<Button>)>,
+    nodes: Query<(&Upgrade, &Children), With<Button>>,
+    locks: Query<&Lock>,
 ) {
-    for (entity, interaction, children) in &buttons {
-        if *interaction == Interaction::Clicked {
-            let mut icon = None;
-            let mut lock = None;
+    let (mut upgrades, mut loadout, controls) = if let Ok(tank) = tanks.get_mut(owner.0) {
+        tank
+    } else {
+        return;
+    };
 
-            for child in children {
-                if let Ok(actual_icon) = icons.get(*child) {
-                    icon = Some(actual_icon);
-                } else if let Ok((lock_entity, _)) = locks.get(*child) {
-                    lock = Some(lock_entity);
-                }
-            }
+    let mut pressed: Vec<_> = buttons
+        .iter()
+        .filter(|(interaction, _, _)| **interaction == Interaction::Clicked)
+        .map(|(_, upgrade, children)| (upgrade, children))
+        .collect();
+    if input.tree_buy(*controls) {
+        pressed.extend(
+            nodes
+                .iter()
+                .filter(|(upgrade, _)| **upgrade == cursor.upgrade()),
+        );
+    }
+
+    for (upgrade, children) in pressed {
+        for child in children {
+            if let Ok(lock) = locks.get(*child) {
+                if lock.0 && coins.0 >= upgrade.cost() {
+                    upgrades.0.push(*upgrade);
+                    *loadout = upgrades.loadout(&modifiers);
+
+                    tree_events.send(TreeEvent(*upgrade));
 
-            let UiImage(icon_image) = icon.unwrap();
-            if let Some(lock) = lock {
-                let (lock_entity, lock) = locks.get(lock).unwrap();
-
-                if lock.0 && coins.0 >= lock.1 {
-                    if icon_image.clone() == sprites.bullet_type[0].clone() {
-                        *bullet_type = BulletType::Rocket;
-                        damage.0 = 100.0;
-                        knockback.0 = 2000.0;
-                        let duration = shoot_timer.duration();
-                        shoot_timer.set_duration(duration - Duration::from_secs_f32(0.0625));
-                    } else if icon_image.clone() == sprites.bullet_type[1].clone() {
-                        *bullet_type = BulletType::SawBlade;
-                        damage.0 = 140.0;
-                        pierce.0 = 4;
-                        knockback.0 = 0.0;
-                        let duration = shoot_timer.duration();
-                        shoot_timer.set_duration(duration - Duration::from_secs_f32(0.03125));
-                    } else if icon_image.clone() == sprites.spread[0].clone() {
-                        spread.next();
-                        let duration = shoot_timer.duration();
-                        shoot_timer.set_duration(duration - Duration::from_secs_f32(0.01));
-                    } else if icon_image.clone() == sprites.spread[1].clone() {
-                        let duration = shoot_timer.duration();
-                        shoot_timer.set_duration(duration - Duration::from_secs_f32(0.015));
-                        spread.next();
-                    }
-
-                    upgrades.0.insert(icon_image.clone());
-
-                    tree_events.send(TreeEvent(entity));
-
-                    coins.0 -= lock.1;
-                    commands.entity(lock_entity).despawn_recursive();
+                    coins.0 -= upgrade.cost();
+                    commands.entity(*child).despawn_recursive();
                 }
             }
         }
@@ -232,28 +689,244 @@ fn handle_button_press(
 
 fn update_locks(
     sprites: Res<Sprites>,
+    meta: Res<MetaProgress>,
+    modifiers: Res<RunModifiers>,
     mut tree_events: EventReader<TreeEvent>,
-    buttons: Query<(&Parent, &Children), With<Button>>,
-    nodes: Query<&Children, With<TreeBranch>>,
+    buttons: Query<(&Upgrade, &Children), With<Button>>,
     mut locks: Query<(&mut Lock, &mut UiImage)>,
 ) {
     for ev in tree_events.iter() {
-        let (parent, _) = buttons.get(ev.0).unwrap();
+        for (upgrade, children) in &buttons {
+            if upgrade.requires() != Some(ev.0)
+                || !upgrade.unlocked(&meta)
+                || !modifiers.allows(*upgrade)
+            {
+                continue;
+            }
 
-        let children = nodes.get(parent.get()).unwrap();
+            for child in children {
+                if let Ok((mut lock, mut image)) = locks.get_mut(*child) {
+                    lock.0 = true;
+                    image.0 = sprites.locks[1].clone();
+                }
+            }
+        }
+    }
+}
 
-        for child in children {
-            if *child != ev.0 {
-                let (_, children) = buttons.get(*child).unwrap();
-
-                for child in children {
-                    if let Ok((mut lock, mut image)) = locks.get_mut(*child) {
-                        lock.0 = true;
-                        image.0 = sprites.locks[1].clone();
-                    }
+fn respec_pressed(
+    owner: Res<TreeOwner>,
+    tanks: Query<&Controls>,
+    input: TankInput,
+    buttons: Query<&Interaction, (Changed<Interaction>, With<RespecButton>)>,
+) -> bool {
+    buttons
+        .iter()
+        .any(|interaction| *interaction == Interaction::Clicked)
+        || tanks
+            .get(owner.0)
+            .map_or(false, |controls| input.tree_respec(*controls))
+}
+
+fn respec(
+    mut commands: Commands,
+    difficulty: Res<Difficulty>,
+    owner: Res<TreeOwner>,
+    mut tanks: Query<(&mut Upgrades, &mut Loadout, &mut Respec)>,
+    mut coins: ResMut<Coins>,
+    modifiers: Res<RunModifiers>,
+    menus: Query<Entity, With<SkillTreeMenu>>,
+    mut respec_events: EventWriter<RespecEvent>,
+) {
+    let (mut upgrades, mut loadout, mut respec) = if let Ok(tank) = tanks.get_mut(owner.0) {
+        tank
+    } else {
+        return;
+    };
+
+    if respec.left(&difficulty) == 0 || upgrades.0.is_empty() {
+        return;
+    }
+
+    let refund = difficulty.refund(upgrades.spent());
+    coins.0 += refund;
+    respec.used += 1;
+
+    upgrades.0.clear();
+    *loadout = upgrades.loadout(&modifiers);
+
+    for menu in &menus {
+        commands.entity(menu).despawn_recursive();
+    }
+    respec_events.send(RespecEvent(refund));
+}
+
+fn update_respec_text(
+    difficulty: Res<Difficulty>,
+    owner: Res<TreeOwner>,
+    tanks: Query<(&Upgrades, &Respec)>,
+    changed: Query<(), Or<(Changed<Upgrades>, Changed<Respec>)>>,
+    mut text: Query<&mut Text, With<RespecText>>,
+) {
+    if let (Ok(mut text), Ok((upgrades, respec))) = (text.get_single_mut(), tanks.get(owner.0)) {
+        if !changed.is_empty() || text.is_added() {
+            let left = respec.left(&difficulty);
+            let available = left > 0 && !upgrades.0.is_empty();
+
+            text.sections[0].value = format!(
+                "Respec: +{} coins ({} left)",
+                difficulty.refund(upgrades.spent()),
+                left
+            );
+            text.sections[0].style.color = if available { Color::WHITE } else { Color::GRAY };
+        }
+    }
+}
+
+fn show_tooltip(
+    mut commands: Commands,
+    fonts: Res<Fonts>,
+    owner: Res<TreeOwner>,
+    tanks: Query<(&Upgrades, &Loadout, &Controls)>,
+    meta: Res<MetaProgress>,
+    modifiers: Res<RunModifiers>,
+    cursor: Res<TreeCursor>,
+    buttons: Query<(&Interaction, &Upgrade), (Changed<Interaction>, With<Button>)>,
+    tooltips: Query<Entity, With<Tooltip>>,
+) {
+    let (upgrades, loadout, controls) = if let Ok(tank) = tanks.get(owner.0) {
+        tank
+    } else {
+        return;
+    };
+
+    // the mouse shows the hovered node, a gamepad whichever one it has selected
+    let shown = match controls {
+        Controls::Mouse if !buttons.is_empty() => buttons
+            .iter()
+            .find(|(interaction, _)| **interaction == Interaction::Hovered)
+            .map(|(_, upgrade)| *upgrade),
+        Controls::Gamepad(_) if cursor.is_changed() || tooltips.is_empty() => {
+            Some(cursor.upgrade())
+        }
+        _ => return,
+    };
+
+    for tooltip in &tooltips {
+        commands.entity(tooltip).despawn_recursive();
+    }
+
+    if let Some(upgrade) = &shown {
+        let line = |text: String, color: Color, font_size: f32| {
+            TextBundle::from_section(
+                text,
+                TextStyle {
+                    color,
+                    font: fonts.main.clone(),
+                    font_size,
+                },
+            )
+        };
+
+        let mut lines = vec![
+            line(upgrade.name().to_string(), Color::WHITE, 28.0),
+            line(upgrade.description().to_string(), Color::GRAY, 18.0),
+            line(format!("Cost: {}", upgrade.cost()), Color::YELLOW, 20.0),
+        ];
+
+        if !upgrade.unlocked(&meta) {
+            lines.push(line(
+                "Unlock it in the workshop".to_string(),
+                Color::RED,
+                20.0,
+            ));
+        }
+
+        if !modifiers.allows(*upgrade) {
+            lines.push(line(
+                "Not allowed in this run".to_string(),
+                Color::RED,
+                20.0,
+            ));
+        }
+
+        if let Some(required) = upgrade.requires() {
+            let owned = upgrades.0.contains(&required);
+            lines.push(line(
+                format!("Requires: {}", required.name()),
+                if owned { Color::GREEN } else { Color::RED },
+                20.0,
+            ));
+        }
+
+        if upgrades.0.contains(upgrade) {
+            lines.push(line("Owned".to_string(), Color::GREEN, 20.0));
+        } else {
+            // preview it on a copy of the current upgrades, exactly like buying it would
+            let mut after = Upgrades(upgrades.0.clone());
+            after.0.push(*upgrade);
+            let after = after.loadout(&modifiers);
+
+            for ((name, before), (_, after)) in loadout.stats().into_iter().zip(after.stats()) {
+                if before != after {
+                    lines.push(line(
+                        format!("{}: {} -> {}", name, before, after),
+                        Color::CYAN,
+                        20.0,
+                    ));
                 }
             }
         }
+
+        commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor(Color::rgba(0.0, 0.0, 0.0, 0.85)),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::ColumnReverse,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                focus_policy: FocusPolicy::Pass,
+                ..default()
+            })
+            .insert(Tooltip)
+            .insert(SkillTreeMenu)
+            .with_children(|parent| {
+                for line in lines {
+                    parent.spawn_bundle(line);
+                }
+            });
+    }
+}
+
+fn move_tooltip(
+    windows: Res<Windows>,
+    owner: Res<TreeOwner>,
+    tanks: Query<&Controls>,
+    tree_cursor: Res<TreeCursor>,
+    buttons: Query<(&Upgrade, &GlobalTransform), With<Button>>,
+    mut tooltips: Query<&mut Style, With<Tooltip>>,
+) {
+    let window = windows.primary();
+
+    // a gamepad's tooltip sits next to the selected node instead of the mouse
+    let cursor = match tanks.get(owner.0) {
+        Ok(Controls::Gamepad(_)) => buttons
+            .iter()
+            .find(|(upgrade, _)| **upgrade == tree_cursor.upgrade())
+            .map(|(_, transform)| transform.translation().truncate() + Vec2::new(30.0, -30.0)),
+        _ => window.cursor_position(),
+    };
+
+    if let Some(cursor) = cursor {
+        for mut style in &mut tooltips {
+            style.position = UiRect {
+                left: Val::Px(cursor.x + 20.0),
+                top: Val::Px(window.height() - cursor.y + 20.0),
+                ..default()
+            };
+        }
     }
 }
 
@@ -270,15 +943,16 @@ fn pause(
     >,
 ) {
     for (entity, mut rigid_body, velocity, force) in &mut entities {
+        commands.entity(entity).insert(PrevBody(*rigid_body));
         *rigid_body = RigidBody::Fixed;
         if let Some(velocity) = velocity {
             commands
                 .entity(entity)
-                .insert(PrevVelocity(velocity.clone()));
+                .insert(PrevVelocity(*velocity));
         }
 
         if let Some(mut force) = force {
-            commands.entity(entity).insert(PrevForce(force.clone()));
+            commands.entity(entity).insert(PrevForce(*force));
             force.force = Vec2::ZERO;
         }
     }
@@ -294,24 +968,82 @@ fn unpause(
             Option<&PrevForce>,
             Option<&mut Velocity>,
             Option<&PrevVelocity>,
+            Option<&PrevBody>,
         ),
         Without<Player>,
     >,
 ) {
-    for (entity, mut rigid_body, force, prev_force, velocity, prev_velocity) in &mut entities {
-        *rigid_body = RigidBody::Dynamic;
+    for (entity, mut rigid_body, force, prev_force, velocity, prev_velocity, prev_body) in
+        &mut entities
+    {
+        // anything spawned while the tree was open was never frozen, so there's nothing to put back
+        if let Some(prev) = prev_body {
+            *rigid_body = prev.0;
+        }
 
-        if let Some(mut force) = force {
-            *force = prev_force.unwrap().0.clone();
+        if let (Some(mut force), Some(prev)) = (force, prev_force) {
+            *force = prev.0;
         }
 
-        if let Some(mut velocity) = velocity {
-            *velocity = prev_velocity.unwrap().0.clone();
+        if let (Some(mut velocity), Some(prev)) = (velocity, prev_velocity) {
+            *velocity = prev.0;
         }
 
         commands
             .entity(entity)
             .remove::<PrevForce>()
-            .remove::<PrevVelocity>();
+            .remove::<PrevVelocity>()
+            .remove::<PrevBody>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{resources::DifficultyPreset, Modifier};
+
+    #[test]
+    fn respec_reverts_every_upgrade() {
+        let modifiers = RunModifiers::default();
+        let fresh = Upgrades::default().loadout(&modifiers);
+
+        let mut upgrades = Upgrades(vec![Upgrade::Rocket, Upgrade::Crit1, Upgrade::Fan]);
+        let upgraded = upgrades.loadout(&modifiers);
+        assert!(matches!(upgraded.bullet_type, BulletType::Rocket));
+        assert_eq!(upgraded.damage.0, 100.0);
+        assert_eq!(upgraded.pattern.shots, 3);
+        assert!(upgraded.crit.chance > fresh.crit.chance);
+
+        upgrades.0.clear();
+        let respecced = upgrades.loadout(&modifiers);
+        assert!(matches!(respecced.bullet_type, BulletType::Regular));
+        assert_eq!(respecced.damage.0, fresh.damage.0);
+        assert_eq!(respecced.pattern.shots, fresh.pattern.shots);
+        assert_eq!(respecced.crit.chance, fresh.crit.chance);
+        assert_eq!(respecced.fire_interval, fresh.fire_interval);
+    }
+
+    #[test]
+    fn modifiers_scale_the_damage_upgrades_set() {
+        let modifiers = RunModifiers {
+            modifiers: vec![Modifier::GlassCannon],
+            ..default()
+        };
+
+        let loadout = Upgrades(vec![Upgrade::Rocket]).loadout(&modifiers);
+        assert_eq!(loadout.damage.0, 200.0);
+    }
+
+    #[test]
+    fn respecs_follow_the_difficulty() {
+        let normal = Difficulty::preset(DifficultyPreset::Normal);
+        assert_eq!(normal.refund(100), 75);
+
+        let mut respec = Respec::default();
+        assert_eq!(respec.left(&normal), 2);
+
+        respec.used = 2;
+        assert_eq!(respec.left(&normal), 0);
+        assert_eq!(respec.left(&Difficulty::preset(DifficultyPreset::Easy)), 1);
     }
 }
@@ -0,0 +1,346 @@
+use bevy::prelude::*;
+use bevy_pkv::PkvStore;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    button_pressed,
+    components::BulletType,
+    despawn_with,
+    resources::{Fonts, Score, Settings, SurvivalTime},
+    update_buttons, CoinEvent, DamageEvent, DamageKind, GameState, KillEvent, RespecEvent,
+    TreeEvent,
+};
+
+// sent for every bullet that leaves a barrel
+pub struct ShotEvent;
+
+// sent whenever a bullet damages an enemy
+pub struct HitEvent {
+    pub bullet_type: BulletType,
+    pub damage: f32,
+}
+
+// numbers for balancing, collected over a single run
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunStats {
+    pub score: u32,
+    pub survival_time: f32,
+    pub kills: u32,
+    pub shots_fired: u32,
+    pub hits: u32,
+    pub bullet_damage: f32,
+    pub rocket_damage: f32,
+    pub sawblade_damage: f32,
+    pub damage_taken: f32,
+    pub coins_earned: u32,
+    pub coins_spent: u32,
+    pub skill_tree_time: f32,
+}
+
+impl RunStats {
+    pub fn accuracy(&self) -> f32 {
+        if self.shots_fired == 0 {
+            0.0
+        } else {
+            self.hits as f32 / self.shots_fired as f32
+        }
+    }
+
+    fn add(&mut self, other: &RunStats) {
+        self.score += other.score;
+        self.survival_time += other.survival_time;
+        self.kills += other.kills;
+        self.shots_fired += other.shots_fired;
+        self.hits += other.hits;
+        self.bullet_damage += other.bullet_damage;
+        self.rocket_damage += other.rocket_damage;
+        self.sawblade_damage += other.sawblade_damage;
+        self.damage_taken += other.damage_taken;
+        self.coins_earned += other.coins_earned;
+        self.coins_spent += other.coins_spent;
+        self.skill_tree_time += other.skill_tree_time;
+    }
+
+    // one line per stat, for the game over screen
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!("Kills: {}", self.kills),
+            format!(
+                "Shots: {} ({:.0}% hit)",
+                self.shots_fired,
+                self.accuracy() * 100.0
+            ),
+            format!(
+                "Damage: {:.0} bullets, {:.0} rockets, {:.0} sawblades",
+                self.bullet_damage, self.rocket_damage, self.sawblade_damage
+            ),
+            format!("Damage taken: {:.0}", self.damage_taken),
+            format!(
+                "Coins: {} earned, {} spent",
+                self.coins_earned, self.coins_spent
+            ),
+            format!("Time in skill tree: {:.0}s", self.skill_tree_time),
+        ]
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn csv_header() -> &'static str {
+        "timestamp,score,survival_time,kills,shots_fired,hits,accuracy,bullet_damage,\
+         rocket_damage,sawblade_damage,damage_taken,coins_earned,coins_spent,skill_tree_time"
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn csv_row(&self, timestamp: u64) -> String {
+        format!(
+            "{},{},{:.2},{},{},{},{:.3},{:.0},{:.0},{:.0},{:.0},{},{},{:.2}",
+            timestamp,
+            self.score,
+            self.survival_time,
+            self.kills,
+            self.shots_fired,
+            self.hits,
+            self.accuracy(),
+            self.bullet_damage,
+            self.rocket_damage,
+            self.sawblade_damage,
+            self.damage_taken,
+            self.coins_earned,
+            self.coins_spent,
+            self.skill_tree_time
+        )
+    }
+}
+
+// totals over every run ever played, saved under "lifetime_stats"
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifetimeStats {
+    pub runs: u32,
+    pub totals: RunStats,
+}
+
+#[derive(Component)]
+struct StatsMenu;
+
+#[derive(Component)]
+struct BackButton;
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ShotEvent>()
+            .add_event::<HitEvent>()
+            .init_resource::<RunStats>()
+            .add_enter_system(GameState::Menu, reset_stats)
+            .add_system(track_stats.run_not_in_state(GameState::Loading))
+            .add_system(track_skill_tree_time.run_in_state(GameState::SkillTree))
+            .add_enter_system(GameState::GameOver, finish_run)
+            .add_enter_system(GameState::Stats, spawn_lifetime_stats)
+            .add_exit_system(GameState::Stats, despawn_with::<StatsMenu>)
+            .add_system(update_buttons.run_in_state(GameState::Stats))
+            .add_system(
+                to_menu
+                    .run_in_state(GameState::Stats)
+                    .run_if(button_pressed::<BackButton>),
+            );
+    }
+}
+
+fn reset_stats(mut stats: ResMut<RunStats>) {
+    *stats = default();
+}
+
+fn track_stats(
+    mut stats: ResMut<RunStats>,
+    mut shot_events: EventReader<ShotEvent>,
+    mut hit_events: EventReader<HitEvent>,
+    mut damage_events: EventReader<DamageEvent>,
+    mut kill_events: EventReader<KillEvent>,
+    mut coin_events: EventReader<CoinEvent>,
+    mut tree_events: EventReader<TreeEvent>,
+    mut respec_events: EventReader<RespecEvent>,
+) {
+    stats.shots_fired += shot_events.iter().count() as u32;
+    stats.kills += kill_events.iter().count() as u32;
+    stats.coins_earned += coin_events.iter().count() as u32;
+
+    for ev in hit_events.iter() {
+        stats.hits += 1;
+        match ev.bullet_type {
+            BulletType::Regular => stats.bullet_damage += ev.damage,
+            BulletType::Rocket => stats.rocket_damage += ev.damage,
+            BulletType::SawBlade => stats.sawblade_damage += ev.damage,
+        }
+    }
+
+    for ev in damage_events.iter() {
+        if let DamageKind::Player = ev.kind {
+            stats.damage_taken += ev.amount;
+        }
+    }
+
+    for ev in tree_events.iter() {
+        stats.coins_spent += ev.0.cost();
+    }
+
+    // what a respec gives back wasn't really spent
+    for ev in respec_events.iter() {
+        stats.coins_spent = stats.coins_spent.saturating_sub(ev.0);
+    }
+}
+
+fn track_skill_tree_time(time: Res<Time>, mut stats: ResMut<RunStats>) {
+    stats.skill_tree_time += time.delta_seconds();
+}
+
+fn finish_run(
+    mut stats: ResMut<RunStats>,
+    score: Res<Score>,
+    survival_time: Res<SurvivalTime>,
+    settings: Res<Settings>,
+    mut pkv: ResMut<PkvStore>,
+) {
+    stats.score = score.0;
+    stats.survival_time = survival_time.elapsed_secs();
+
+    let mut lifetime = pkv
+        .get::<LifetimeStats>("lifetime_stats")
+        .unwrap_or_default();
+    lifetime.runs += 1;
+    lifetime.totals.add(&stats);
+    pkv.set("lifetime_stats", &lifetime)
+        .expect("failed to access pkv store");
+
+    if settings.export_stats {
+        if let Err(err) = export(&stats) {
+            warn!("failed to export run stats: {}", err);
+        }
+    }
+}
+
+fn spawn_lifetime_stats(mut commands: Commands, fonts: Res<Fonts>, pkv: Res<PkvStore>) {
+    let lifetime = pkv
+        .get::<LifetimeStats>("lifetime_stats")
+        .unwrap_or_default();
+
+    let text_style = TextStyle {
+        font: fonts.main.clone(),
+        font_size: 30.0,
+        color: Color::WHITE,
+    };
+
+    let mut lines = vec![
+        format!("Runs: {}", lifetime.runs),
+        format!("Score: {}", lifetime.totals.score),
+        format!("Time survived: {:.0}s", lifetime.totals.survival_time),
+    ];
+    lines.extend(lifetime.totals.lines());
+
+    commands
+        .spawn_bundle(NodeBundle {
+            color: UiColor([0.0; 4].into()),
+            style: Style {
+                margin: UiRect::all(Val::Auto),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(StatsMenu)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                "Lifetime Stats",
+                TextStyle {
+                    color: Color::YELLOW,
+                    font_size: 50.0,
+                    ..text_style.clone()
+                },
+            ));
+
+            for line in lines {
+                parent.spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: UiRect::all(Val::Px(4.0)),
+                        ..default()
+                    },
+                    text: Text::from_section(line, text_style.clone()),
+                    ..default()
+                });
+            }
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+                        margin: UiRect::all(Val::Px(6.0)),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(BackButton)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section(
+                        "Back",
+                        TextStyle {
+                            font_size: 35.0,
+                            ..text_style.clone()
+                        },
+                    ));
+                });
+        });
+}
+
+fn to_menu(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Menu));
+}
+
+// there's no file system to write to on the web
+#[cfg(target_arch = "wasm32")]
+fn export(_stats: &RunStats) -> std::io::Result<()> {
+    Ok(())
+}
+
+// appends the run to run_stats.csv and run_stats.jsonl in the working directory
+#[cfg(not(target_arch = "wasm32"))]
+fn export(stats: &RunStats) -> std::io::Result<()> {
+    use std::{
+        fs::OpenOptions,
+        io::Write,
+        path::Path,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+
+    let csv_path = Path::new("run_stats.csv");
+    let new_file = !csv_path.exists();
+    let mut csv = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(csv_path)?;
+    if new_file {
+        writeln!(csv, "{}", RunStats::csv_header())?;
+    }
+    writeln!(csv, "{}", stats.csv_row(timestamp))?;
+
+    let mut json = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open("run_stats.jsonl")?;
+    writeln!(
+        json,
+        "{}",
+        serde_json::json!({ "timestamp": timestamp, "stats": stats })
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,261 @@
+use bevy::prelude::*;
+use bevy_pkv::PkvStore;
+use iyes_loopless::prelude::*;
+
+use crate::{
+    button_pressed, despawn_with,
+    resources::{Fonts, MetaProgress, SavedMeta, Score, SurvivalTime, META_VERSION},
+    update_buttons, GameState,
+};
+
+#[derive(Component)]
+struct WorkshopMenu;
+
+#[derive(Component)]
+struct BackButton;
+
+#[derive(Component, Clone, Copy)]
+enum MetaUpgrade {
+    Armor,
+    Savings,
+    Frost,
+    Leech,
+}
+
+impl MetaUpgrade {
+    const ALL: [MetaUpgrade; 4] = [
+        MetaUpgrade::Armor,
+        MetaUpgrade::Savings,
+        MetaUpgrade::Frost,
+        MetaUpgrade::Leech,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            MetaUpgrade::Armor => "Armor",
+            MetaUpgrade::Savings => "Savings",
+            MetaUpgrade::Frost => "Frost Rounds",
+            MetaUpgrade::Leech => "Leech Rounds",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            MetaUpgrade::Armor => "+25 starting health",
+            MetaUpgrade::Savings => "+10 starting coins",
+            MetaUpgrade::Frost => "Adds frost rounds to the skill tree",
+            MetaUpgrade::Leech => "Adds leech rounds to the skill tree",
+        }
+    }
+
+    fn max_level(&self) -> u32 {
+        match self {
+            MetaUpgrade::Armor => 4,
+            MetaUpgrade::Savings => 5,
+            MetaUpgrade::Frost | MetaUpgrade::Leech => 1,
+        }
+    }
+
+    fn level(&self, meta: &MetaProgress) -> u32 {
+        match self {
+            MetaUpgrade::Armor => meta.armor,
+            MetaUpgrade::Savings => meta.savings,
+            MetaUpgrade::Frost => meta.frost as u32,
+            MetaUpgrade::Leech => meta.leech as u32,
+        }
+    }
+
+    // cost of the next level
+    fn cost(&self, meta: &MetaProgress) -> u32 {
+        let level = self.level(meta);
+
+        match self {
+            MetaUpgrade::Armor => 50 * (level + 1),
+            MetaUpgrade::Savings => 40 * (level + 1),
+            MetaUpgrade::Frost => 150,
+            MetaUpgrade::Leech => 200,
+        }
+    }
+
+    fn buy(&self, meta: &mut MetaProgress) {
+        match self {
+            MetaUpgrade::Armor => meta.armor += 1,
+            MetaUpgrade::Savings => meta.savings += 1,
+            MetaUpgrade::Frost => meta.frost = true,
+            MetaUpgrade::Leech => meta.leech = true,
+        }
+    }
+}
+
+pub struct WorkshopPlugin;
+
+impl Plugin for WorkshopPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MetaProgress>()
+            .add_startup_system(load_meta)
+            .add_enter_system(GameState::GameOver, award_scrap)
+            .add_enter_system(GameState::Workshop, spawn_workshop)
+            .add_exit_system(GameState::Workshop, despawn_with::<WorkshopMenu>)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Workshop)
+                    .with_system(update_buttons)
+                    .with_system(buy_meta_upgrade)
+                    .into(),
+            )
+            .add_system(
+                to_menu
+                    .run_in_state(GameState::Workshop)
+                    .run_if(button_pressed::<BackButton>),
+            );
+    }
+}
+
+fn load_meta(mut meta: ResMut<MetaProgress>, pkv: Res<PkvStore>) {
+    if let Ok(saved) = pkv.get::<SavedMeta>("meta") {
+        match saved.version {
+            META_VERSION => *meta = saved.progress,
+            // migrations from older versions go here
+            version => warn!(
+                "ignoring meta progress saved with unknown version {}",
+                version
+            ),
+        }
+    }
+}
+
+fn save_meta(meta: &MetaProgress, pkv: &mut PkvStore) {
+    pkv.set(
+        "meta",
+        &SavedMeta {
+            version: META_VERSION,
+            progress: meta.clone(),
+        },
+    )
+    .expect("failed to access pkv store");
+}
+
+fn award_scrap(
+    mut meta: ResMut<MetaProgress>,
+    mut pkv: ResMut<PkvStore>,
+    score: Res<Score>,
+    survival_time: Res<SurvivalTime>,
+) {
+    meta.scrap += MetaProgress::reward(score.0, survival_time.elapsed_secs());
+    save_meta(&meta, &mut pkv);
+}
+
+fn spawn_workshop(mut commands: Commands, fonts: Res<Fonts>, meta: Res<MetaProgress>) {
+    let text_style = TextStyle {
+        font: fonts.main.clone(),
+        font_size: 35.0,
+        color: Color::WHITE,
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            color: UiColor([0.0; 4].into()),
+            style: Style {
+                margin: UiRect::all(Val::Auto),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(WorkshopMenu)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                format!("Scrap: {}", meta.scrap),
+                TextStyle {
+                    color: Color::YELLOW,
+                    font_size: 50.0,
+                    ..text_style.clone()
+                },
+            ));
+
+            for upgrade in MetaUpgrade::ALL {
+                let level = upgrade.level(&meta);
+                let label = if level >= upgrade.max_level() {
+                    format!("{} (max)", upgrade.name())
+                } else {
+                    format!(
+                        "{} {}/{} - {} scrap",
+                        upgrade.name(),
+                        level,
+                        upgrade.max_level(),
+                        upgrade.cost(&meta)
+                    )
+                };
+
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(500.0), Val::Px(65.0)),
+                            margin: UiRect::all(Val::Px(6.0)),
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::Center,
+                            flex_direction: FlexDirection::ColumnReverse,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .insert(upgrade)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle::from_section(label, text_style.clone()));
+                        parent.spawn_bundle(TextBundle::from_section(
+                            upgrade.description(),
+                            TextStyle {
+                                color: Color::GRAY,
+                                font_size: 20.0,
+                                ..text_style.clone()
+                            },
+                        ));
+                    });
+            }
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+                        margin: UiRect::all(Val::Px(6.0)),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(BackButton)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section("Back", text_style.clone()));
+                });
+        });
+}
+
+fn buy_meta_upgrade(
+    mut commands: Commands,
+    mut meta: ResMut<MetaProgress>,
+    mut pkv: ResMut<PkvStore>,
+    buttons: Query<(&Interaction, &MetaUpgrade), (Changed<Interaction>, With<Button>)>,
+) {
+    for (interaction, upgrade) in &buttons {
+        let cost = upgrade.cost(&meta);
+
+        if *interaction == Interaction::Clicked
+            && upgrade.level(&meta) < upgrade.max_level()
+            && meta.scrap >= cost
+        {
+            meta.scrap -= cost;
+            upgrade.buy(&mut meta);
+            save_meta(&meta, &mut pkv);
+
+            // re-entering the state rebuilds the labels
+            commands.insert_resource(NextState(GameState::Workshop));
+        }
+    }
+}
+
+fn to_menu(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Menu));
+}
@@ -1,7 +1,9 @@
-use std::f32::consts::PI;
-
-use bevy::prelude::*;
+use bevy::{prelude::*, time::Stopwatch};
 use bevy_asset_loader::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// the world is laid out in this fixed virtual resolution, the camera scales it to fit the window
+pub const ARENA_SIZE: Vec2 = Vec2::new(1280.0, 720.0);
 
 #[derive(AssetCollection)]
 pub struct Fonts {
@@ -11,17 +13,61 @@ pub struct Fonts {
 
 #[derive(AssetCollection)]
 pub struct Sprites {
-    #[asset(texture_atlas(tile_size_x = 16., tile_size_y = 16., columns = 2, rows = 1))]
+    // idle, firing and overheated
+    #[asset(texture_atlas(tile_size_x = 16., tile_size_y = 16., columns = 3, rows = 1))]
     #[asset(path = "sprites/barrel-sheet.png")]
     pub barrel: Handle<TextureAtlas>,
     #[asset(paths("sprites/rocket.png", "sprites/sawblade.png"), collection(typed))]
     pub bullet_type: Vec<Handle<Image>>,
+    #[asset(
+        paths("sprites/boomerang.png", "sprites/ricochet.png"),
+        collection(typed)
+    )]
+    pub saw_tricks: Vec<Handle<Image>>,
     #[asset(paths("sprites/spread1.png", "sprites/spread2.png"), collection(typed))]
     pub spread: Vec<Handle<Image>>,
+    #[asset(paths("sprites/homing1.png", "sprites/homing2.png"), collection(typed))]
+    pub homing: Vec<Handle<Image>>,
+    #[asset(paths("sprites/fan.png", "sprites/burst.png"), collection(typed))]
+    pub multishot: Vec<Handle<Image>>,
+    #[asset(paths("sprites/twin.png", "sprites/rear.png"), collection(typed))]
+    pub guns: Vec<Handle<Image>>,
+    #[asset(paths("sprites/crit1.png", "sprites/crit2.png"), collection(typed))]
+    pub crit: Vec<Handle<Image>>,
     #[asset(paths("sprites/ice.png", "sprites/suc.png"), collection(typed))]
     pub effects: Vec<Handle<Image>>,
     #[asset(paths("sprites/lock.png", "sprites/unlock.png"), collection(typed))]
     pub locks: Vec<Handle<Image>>,
+    #[asset(paths("sprites/turret1.png", "sprites/turret2.png"), collection(typed))]
+    pub turrets: Vec<Handle<Image>>,
+    #[asset(paths("sprites/drone1.png", "sprites/drone2.png"), collection(typed))]
+    pub drones: Vec<Handle<Image>>,
+    #[asset(paths("sprites/shockwave.png", "sprites/emp.png"), collection(typed))]
+    pub blasts: Vec<Handle<Image>>,
+    #[asset(
+        paths("sprites/magnet.png", "sprites/overdrive.png"),
+        collection(typed)
+    )]
+    pub boosts: Vec<Handle<Image>>,
+    #[asset(
+        paths("sprites/heatsink.png", "sprites/coolant.png"),
+        collection(typed)
+    )]
+    pub cooling: Vec<Handle<Image>>,
+    #[asset(paths("sprites/charge1.png", "sprites/charge2.png"), collection(typed))]
+    pub charge: Vec<Handle<Image>>,
+    // in the order of `PowerUp`
+    #[asset(
+        paths(
+            "sprites/heal.png",
+            "sprites/rapid.png",
+            "sprites/triple.png",
+            "sprites/shield.png",
+            "sprites/nuke.png"
+        ),
+        collection(typed)
+    )]
+    pub power_ups: Vec<Handle<Image>>,
     #[asset(path = "sprites/rocket.png")]
     pub rocket: Handle<Image>,
     #[asset(path = "sprites/sawblade.png")]
@@ -30,10 +76,16 @@ pub struct Sprites {
     pub bullet: Handle<Image>,
     #[asset(path = "sprites/coin.png")]
     pub coin: Handle<Image>,
+    #[asset(path = "sprites/drone.png")]
+    pub drone: Handle<Image>,
     #[asset(path = "sprites/player-base.png")]
     pub base: Handle<Image>,
     #[asset(path = "sprites/zombie.png")]
     pub enemy: Handle<Image>,
+    #[asset(path = "sprites/spitter.png")]
+    pub spitter: Handle<Image>,
+    #[asset(path = "sprites/spit.png")]
+    pub spit: Handle<Image>,
 }
 
 #[derive(AssetCollection)]
@@ -52,34 +104,194 @@ pub struct Sounds {
     pub coin_pickup: Handle<AudioSource>,
 }
 
-pub struct Spread(pub u32, pub f32);
+// persisted in the pkv store under "settings"
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // multiplier for camera shake, 0 turns it off entirely
+    pub screen_shake: f32,
+    // append every run's stats to files in the working directory
+    pub export_stats: bool,
+    // adds a second tank played with the first connected gamepad
+    pub co_op: bool,
+}
+
+impl Settings {
+    pub fn next_screen_shake(&mut self) {
+        self.screen_shake = if self.screen_shake > 0.5 {
+            0.5
+        } else if self.screen_shake > 0.0 {
+            0.0
+        } else {
+            1.0
+        };
+    }
+
+    pub fn screen_shake_label(&self) -> String {
+        if self.screen_shake > 0.0 {
+            format!("{:.0}%", self.screen_shake * 100.0)
+        } else {
+            "Off".to_string()
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            screen_shake: 1.0,
+            export_stats: false,
+            co_op: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DifficultyPreset {
+    Easy,
+    Normal,
+    Hard,
+    Nightmare,
+    Custom,
+}
+
+impl DifficultyPreset {
+    pub const ALL: [DifficultyPreset; 5] = [
+        DifficultyPreset::Easy,
+        DifficultyPreset::Normal,
+        DifficultyPreset::Hard,
+        DifficultyPreset::Nightmare,
+        DifficultyPreset::Custom,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DifficultyPreset::Easy => "Easy",
+            DifficultyPreset::Normal => "Normal",
+            DifficultyPreset::Hard => "Hard",
+            DifficultyPreset::Nightmare => "Nightmare",
+            DifficultyPreset::Custom => "Custom",
+        }
+    }
+}
+
+// how fast enemies ramp up and what they start at, persisted under "difficulty"
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Difficulty {
+    pub preset: DifficultyPreset,
+    // `EnemyScale` is multiplied by this every second
+    pub scale_growth: f32,
+    // the spawn interval is divided by this every second
+    pub spawn_speedup: f32,
+    pub enemy_damage: f32,
+    pub enemy_health: f32,
+    pub player_health: f32,
+    // share of the spent coins a respec gives back
+    pub respec_refund: f32,
+    // respecs per run
+    pub respecs: u32,
+}
+
+impl Difficulty {
+    pub fn preset(preset: DifficultyPreset) -> Difficulty {
+        let (scale_growth, spawn_speedup, enemy_damage, enemy_health, player_health) = match preset
+        {
+            DifficultyPreset::Easy => (1.003, 1.005, 6.0, 80.0, 300.0),
+            DifficultyPreset::Normal | DifficultyPreset::Custom => {
+                (1.005, 1.01, 10.0, 100.0, 200.0)
+            }
+            DifficultyPreset::Hard => (1.007, 1.015, 14.0, 130.0, 150.0),
+            DifficultyPreset::Nightmare => (1.01, 1.02, 20.0, 160.0, 100.0),
+        };
+        let (respec_refund, respecs) = match preset {
+            DifficultyPreset::Easy => (0.9, 3),
+            DifficultyPreset::Normal | DifficultyPreset::Custom => (0.75, 2),
+            DifficultyPreset::Hard => (0.5, 1),
+            DifficultyPreset::Nightmare => (0.25, 1),
+        };
 
-impl Spread {
-    pub fn next(&mut self) {
-        if self.0 == 0 {
-            self.1 = PI / 16.0;
+        Difficulty {
+            preset,
+            scale_growth,
+            spawn_speedup,
+            enemy_damage,
+            enemy_health,
+            player_health,
+            respec_refund,
+            respecs,
+        }
+    }
+
+    // coins a respec gives back for what was spent
+    pub fn refund(&self, spent: u32) -> u32 {
+        (spent as f32 * self.respec_refund) as u32
+    }
 
-            self.0 += 1;
-        } else if self.0 == 1 {
-            self.1 = 0.0;
+    // every difficulty keeps its own high score, "high_score" itself is left alone since it
+    // counted the coins left at the end of a run rather than the score
+    pub fn high_score_key(&self) -> String {
+        let key = format!("best_score_{}", self.preset.name().to_lowercase());
 
-            self.0 += 1;
+        // custom difficulties only share a high score when every stat matches
+        match self.preset {
+            DifficultyPreset::Custom => format!(
+                "{}_{:.3}_{:.3}_{:.0}_{:.0}_{:.0}_{:.2}_{}",
+                key,
+                self.scale_growth,
+                self.spawn_speedup,
+                self.enemy_damage,
+                self.enemy_health,
+                self.player_health,
+                self.respec_refund,
+                self.respecs
+            ),
+            _ => key,
         }
     }
 }
 
-impl Default for Spread {
-    fn default() -> Spread {
-        Spread(0, PI / 8.0)
+impl Default for Difficulty {
+    fn default() -> Difficulty {
+        Difficulty::preset(DifficultyPreset::Normal)
     }
 }
 
-#[derive(Default, Clone, Copy)]
-pub enum BulletType {
-    #[default]
-    Regular = 0,
-    Rocket = 1,
-    SawBlade = 2,
+// bump this whenever `MetaProgress` changes in a way `serde(default)` can't paper over
+pub const META_VERSION: u32 = 1;
+
+// permanent progress that survives between runs, bought with scrap in the workshop
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct MetaProgress {
+    pub scrap: u32,
+    pub armor: u32,
+    pub savings: u32,
+    pub frost: bool,
+    pub leech: bool,
+}
+
+impl MetaProgress {
+    // added on top of the difficulty's player health
+    pub fn bonus_health(&self) -> f32 {
+        25.0 * self.armor as f32
+    }
+
+    pub fn starting_coins(&self) -> u32 {
+        10 * self.savings
+    }
+
+    // scrap earned at the end of a run
+    pub fn reward(score: u32, seconds: f32) -> u32 {
+        score / 5 + seconds as u32 / 15
+    }
+}
+
+// what actually goes into the pkv store, the version tells old saves apart
+#[derive(Serialize, Deserialize)]
+pub struct SavedMeta {
+    pub version: u32,
+    pub progress: MetaProgress,
 }
 
 #[derive(Default)]
@@ -88,6 +300,13 @@ pub struct MousePosition(pub Vec2);
 #[derive(Default)]
 pub struct Coins(pub u32);
 
+// every coin picked up this run, unlike `Coins` this doesn't go down when upgrades are bought
+#[derive(Default)]
+pub struct Score(pub u32);
+
+#[derive(Default, Deref, DerefMut)]
+pub struct SurvivalTime(pub Stopwatch);
+
 #[derive(Deref, DerefMut)]
 pub struct ScaleTimer(pub Timer);
 
@@ -99,15 +318,6 @@ impl Default for EnemyScale {
     }
 }
 
-#[derive(Deref, DerefMut)]
-pub struct ShootTimer(pub Timer);
-
-impl Default for ShootTimer {
-    fn default() -> ShootTimer {
-        ShootTimer(Timer::from_seconds(0.125, true))
-    }
-}
-
 #[derive(Deref, DerefMut)]
 pub struct SpawnTimer(Timer);
 
@@ -116,4 +326,3 @@ impl Default for SpawnTimer {
         SpawnTimer(Timer::from_seconds(1.0, true))
     }
 }
-